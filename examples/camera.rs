@@ -13,17 +13,13 @@ const DUMMY_LEAF: Cell = Cell::leaf(
 fn setup_world(depth: u8) -> World {
     let mut world = World::new(B3S23);
 
-    world.buf.pop();
-    world.buf.push(DUMMY_LEAF);
+    let mut ptr = world.buf.insert(DUMMY_LEAF);
 
-    let n = world.buf.len();
-
-    for i in 0..depth {
-        let i = n + i as usize - 1;
-        world.buf.push(Cell::new(i, i, i, i));
+    for _ in 0..depth {
+        ptr = world.buf.insert(Cell::new(ptr, ptr, ptr, ptr));
     }
 
-    world.root = world.buf.len() - 1;
+    world.root = ptr;
     world.depth = depth;
 
     world
@@ -0,0 +1,34 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use hashlife::parse_rle::read_rle;
+use hashlife::png::export_png;
+use hashlife::rule_set::B3S23;
+use hashlife::world::Rect;
+use hashlife::world::World;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let path = args.get(1).map(Path::new).expect("A .rle file is required");
+    let out = args
+        .get(2)
+        .map(Path::new)
+        .expect("An output .png path is required");
+    let scale = args
+        .get(3)
+        .map_or(1, |s| s.parse().expect("Scale should be a number"));
+
+    let data = read_to_string(path).expect("Failed to open .rle file");
+    let data = data.as_bytes();
+
+    let mut world = World::new(B3S23);
+    world.grow(5);
+
+    read_rle(data, |x, y| world.set(x, y)).expect("Failed to read RLE file");
+
+    let half = 1 << (world.depth - 1);
+    let size = 1 << world.depth;
+    let rect = Rect::new(-half, half - 1, size, size);
+
+    export_png(&world, rect, scale, out).expect("Failed to write PNG file");
+}
@@ -0,0 +1,35 @@
+use hashlife::rule_set::RuleSet;
+use hashlife::soup::SoupSettings;
+use hashlife::soup::Symmetry;
+use hashlife::soup::search;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let prefix = args.get(1).expect("A seed prefix is required, like k_abc");
+    let count = args
+        .get(2)
+        .map_or(100, |s| s.parse().expect("Count should be a number"));
+    let symmetry = args
+        .get(3)
+        .map_or(Ok(Symmetry::C1), |s| s.parse())
+        .expect("Unknown symmetry");
+    let rule: RuleSet = args
+        .get(4)
+        .map_or("B3/S23", String::as_str)
+        .parse()
+        .expect("Failed to parse rule");
+
+    let settings = SoupSettings {
+        symmetry,
+        ..Default::default()
+    };
+
+    let census = search(&rule, prefix, count, &settings);
+
+    let mut counts: Vec<_> = census.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    for (code, n) in counts {
+        println!("{code} {n}");
+    }
+}
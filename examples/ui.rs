@@ -33,6 +33,7 @@ enum Event {
     Next,
     CamResize { cols: u16, rows: u16 },
     ResetView,
+    ToggleDiff,
     Exit,
 }
 
@@ -46,17 +47,13 @@ const DUMMY_LEAF: Cell = Cell::leaf(
 fn setup_world(depth: u8) -> World {
     let mut world = World::new(B3S23);
 
-    world.buf.pop();
-    world.buf.push(DUMMY_LEAF);
+    let mut ptr = world.buf.insert(DUMMY_LEAF);
 
-    let n = world.buf.len();
-
-    for i in 0..depth {
-        let i = n + i as usize - 1;
-        world.buf.push(Cell::new(i, i, i, i));
+    for _ in 0..depth {
+        ptr = world.buf.insert(Cell::new(ptr, ptr, ptr, ptr));
     }
 
-    world.root = world.buf.len() - 1;
+    world.root = ptr;
     world.depth = depth + 3;
 
     world.grow(1);
@@ -111,6 +108,10 @@ fn handle_event(event: CtEvent) -> io::Result<Option<Event>> {
                 code: KeyCode::Char(' '),
                 ..
             } => Ok(Some(Event::Next)),
+            KeyEvent {
+                code: KeyCode::Char('d'),
+                ..
+            } => Ok(Some(Event::ToggleDiff)),
             _ => Ok(None),
         },
         CtEvent::Resize(cols, rows) => Ok(Some(Event::CamResize { cols, rows })),
@@ -128,6 +129,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut cam = Camera::new(cols, rows);
     let mut world = setup_world(2);
 
+    // The previous generation, to show what changed since
+    let mut prev = setup_world(2);
+    let mut show_diff = false;
+
     loop {
         let t = time::SystemTime::now();
 
@@ -156,15 +161,26 @@ fn main() -> Result<(), Box<dyn Error>> {
                 cam.resize(cols, rows);
             }
             Some(Event::Next) => {
+                if world.generation() > 0 {
+                    prev.next();
+                }
+
                 world.next();
             }
             Some(Event::ResetView) => {
                 cam.reset_view();
             }
+            Some(Event::ToggleDiff) => {
+                show_diff = !show_diff;
+            }
         }
 
         cam.reset();
         cam.draw(&world);
+
+        if show_diff {
+            cam.draw_diff(&world, &prev.diff(&world));
+        }
         let s = cam.render();
 
         execute!(
@@ -1,5 +1,7 @@
 use crate::cell::Cell;
-use crate::cell::LEAF_MASK;
+use crate::cell::CellBuf;
+use crate::world::DiffResult;
+use crate::world::Rect;
 use crate::world::World;
 use crate::CellOffset;
 use crate::ScreenSize;
@@ -19,6 +21,20 @@ use crate::WorldOffset;
 /// To get other configurations, just add the numbers above.
 const BRAILLE_EMPTY: u32 = 0x2800;
 
+/// Overlay bits of characters with births and with deaths, see [`Camera::draw_diff`]
+const OVERLAY_BIRTH: u8 = 1 << 0;
+const OVERLAY_DEATH: u8 = 1 << 1;
+
+/// ANSI escape codes of the colours of births, deaths, and characters with both
+const BIRTH_COLOUR: &str = "\x1b[32m";
+const DEATH_COLOUR: &str = "\x1b[31m";
+const MIXED_COLOUR: &str = "\x1b[33m";
+const RESET_COLOUR: &str = "\x1b[0m";
+
+/// ANSI escape codes of the colours of dying Generations states, from state 2 on. Further states
+/// cycle through them again.
+const STATE_COLOURS: [&str; 4] = ["\x1b[34m", "\x1b[35m", "\x1b[36m", "\x1b[90m"];
+
 pub struct Camera {
     /// The cell buffer
     cb: Vec<bool>,
@@ -29,6 +45,13 @@ pub struct Camera {
     /// Codepoints. This allows us to construct the framebuffer more easily
     cp: Vec<u32>,
 
+    /// The overlay buffer. This keeps track of the births and deaths within each character
+    ob: Vec<u8>,
+
+    /// The state buffer. This keeps track of the lowest state drawn within each character, with
+    /// Generations rules
+    sb: Vec<u8>,
+
     /// Column width of the framebuffer
     w: ScreenSize,
 
@@ -68,6 +91,14 @@ impl Camera {
         // The codepoints buffer. This makes it easier to construct the frame buffer later
         let cp = vec![BRAILLE_EMPTY; w * h];
 
+        // The overlay buffer. Characters are drawn in colour once births or deaths are drawn in
+        // them
+        let ob = vec![0; w * h];
+
+        // The state buffer. Characters are drawn in the colour of the lowest state in them, so
+        // that dying cells stand out from live ones
+        let sb = vec![0; w * h];
+
         // For each braille character, we need 3 bytes:
         //  - The leader byte:     0b11100010
         //  - Continuation byte 1: 0b101000xx
@@ -91,6 +122,8 @@ impl Camera {
             cb,
             fb,
             cp,
+            ob,
+            sb,
             w: w as ScreenSize,
             h: h as ScreenSize,
             x: 0,
@@ -107,6 +140,20 @@ impl Camera {
         self.h
     }
 
+    /// The region of `world` currently in view
+    pub fn viewport(&self, world: &World) -> Rect {
+        let half = 1 << (world.depth - 1);
+
+        // Screen offsets of the world root, in world cells
+        let dx = (self.x >> self.scale) << self.scale;
+        let dy = (self.y >> self.scale) << self.scale;
+
+        let w = (2 * self.w as u64) << self.scale;
+        let h = (4 * self.h as u64) << self.scale;
+
+        Rect::new(-dx - half, half - 1 + dy, w, h)
+    }
+
     pub fn move_left(&mut self, n: u64) {
         let dx = 2i128.pow(self.scale as u32);
         self.x += dx * n as i128;
@@ -147,9 +194,17 @@ impl Camera {
 
         self.cp.clear();
         self.cp.resize(w * h, BRAILLE_EMPTY);
+
+        self.ob.clear();
+        self.ob.resize(w * h, 0);
+
+        self.sb.clear();
+        self.sb.resize(w * h, 0);
     }
 
-    /// Draw a [`World`] onto the cell buffer
+    /// Draw a [`World`] onto the cell buffer. With Generations rules, cells are drawn in any
+    /// state but 0, and characters holding only cells in dying states are rendered in the colour
+    /// of the lowest of them.
     pub fn draw(&mut self, world: &World) {
         let buf = &world.buf;
         let root = world.root;
@@ -173,6 +228,69 @@ impl Camera {
             n,
             scale,
         );
+
+        self.draw_grid(world);
+    }
+
+    /// Draw the edges of the [`World::grid`] of bounded topologies, just outside of it. Unbounded
+    /// dimensions have no edges.
+    fn draw_grid(&mut self, world: &World) {
+        let (Some(grid), Some(ext)) = (world.grid(), world.rule().extension()) else {
+            return;
+        };
+
+        let view = self.viewport(world);
+        let (w, h) = (2 * self.w as WorldOffset, 4 * self.h as WorldOffset);
+
+        // Screen pixels of world cells
+        let px = |x: WorldOffset| (x - view.x) >> self.scale;
+        let py = |y: WorldOffset| (view.y - y) >> self.scale;
+
+        let (left, right) = (px(grid.x - 1), px(grid.x + grid.w as WorldOffset));
+        let (top, bottom) = (py(grid.y + 1), py(grid.y - grid.h as WorldOffset));
+
+        type Pixel = (WorldOffset, WorldOffset);
+
+        let line = |cam: &mut Self, (x0, y0): Pixel, (x1, y1): Pixel| {
+            for x in x0.max(0)..=x1.min(w - 1) {
+                for y in y0.max(0)..=y1.min(h - 1) {
+                    cam.draw_pixel(x as CellOffset, y as CellOffset);
+                }
+            }
+        };
+
+        if ext.width > 0 {
+            line(self, (left, top), (left, bottom));
+            line(self, (right, top), (right, bottom));
+        }
+
+        if ext.height > 0 {
+            line(self, (left, top), (right, top));
+            line(self, (left, bottom), (right, bottom));
+        }
+    }
+
+    /// Draw the births and deaths of a [`DiffResult`] onto the cell buffer, on top of `world`.
+    /// Characters with births are rendered in green, characters with deaths in red, and
+    /// characters with both in yellow.
+    pub fn draw_diff(&mut self, world: &World, diff: &DiffResult) {
+        let view = self.viewport(world);
+        let (w, h) = (2 * self.w as WorldOffset, 4 * self.h as WorldOffset);
+
+        for (cells, overlay) in [(&diff.births, OVERLAY_BIRTH), (&diff.deaths, OVERLAY_DEATH)] {
+            for &(x, y) in cells {
+                let (x, y) = ((x - view.x) >> self.scale, (view.y - y) >> self.scale);
+
+                if x < 0 || y < 0 || x >= w || y >= h {
+                    continue;
+                }
+
+                self.draw_pixel(x as CellOffset, y as CellOffset);
+
+                let i = (y / 4) as usize * self.w as usize + (x / 2) as usize;
+                self.ob[i] |= overlay;
+            }
+        }
     }
 
     pub fn zoom_in(&mut self) {
@@ -239,9 +357,27 @@ impl Camera {
         self.rect_set(x, y, s, false)
     }
 
-    /// Reset the cell buffer
+    /// Reset the cell buffer, the overlay buffer and the state buffer
     pub fn reset(&mut self) {
         self.cb.fill(false);
+        self.ob.fill(0);
+        self.sb.fill(0);
+    }
+
+    /// Keep track of a cell in `state` drawn at pixel (`x`, `y`), for the colour of the
+    /// character holding it. Pixels off the screen are skipped.
+    fn draw_state(&mut self, x: CellOffset, y: CellOffset, state: u8) {
+        let (w, h) = (2 * self.w as CellOffset, 4 * self.h as CellOffset);
+
+        if x < 0 || y < 0 || x >= w || y >= h {
+            return;
+        }
+
+        let i = (y / 4) as usize * self.w as usize + (x / 2) as usize;
+
+        if self.sb[i] == 0 || state < self.sb[i] {
+            self.sb[i] = state;
+        }
     }
 
     pub fn render(&mut self) -> &str {
@@ -270,7 +406,23 @@ impl Camera {
                 self.fb.push('\n');
             }
 
+            let colour = match (self.ob[i], self.sb[i]) {
+                (0, 0 | 1) => None,
+                (0, state) => Some(STATE_COLOURS[(state as usize - 2) % STATE_COLOURS.len()]),
+                (OVERLAY_BIRTH, _) => Some(BIRTH_COLOUR),
+                (OVERLAY_DEATH, _) => Some(DEATH_COLOUR),
+                _ => Some(MIXED_COLOUR),
+            };
+
+            if let Some(colour) = colour {
+                self.fb.push_str(colour);
+            }
+
             self.fb.push(::std::char::from_u32(c).unwrap());
+
+            if colour.is_some() {
+                self.fb.push_str(RESET_COLOUR);
+            }
         }
         self.fb.push('\n');
 
@@ -382,32 +534,53 @@ fn draw_rule(cam: &mut Camera, rule: u16, dx: CellOffset, dy: CellOffset, scale:
     }
 }
 
-fn draw_leaf(cam: &mut Camera, cell: Cell, dx: CellOffset, dy: CellOffset, scale: u32) {
+fn draw_leaf(
+    cam: &mut Camera,
+    buf: &CellBuf,
+    cell: Cell,
+    dx: CellOffset,
+    dy: CellOffset,
+    scale: u32,
+) {
     assert!(cell.is_leaf());
 
+    // Generations leaves hold blocks of states rather than rules
+    let [nw, ne, sw, se] = buf.rules(cell).unwrap_or_default();
+
+    if buf.states() > 2 && scale <= 3 {
+        for (row, states) in buf.leaf_states(cell).iter().enumerate() {
+            for (col, &state) in states.iter().enumerate() {
+                if state != 0 {
+                    let (x, y) = ((col >> scale) as CellOffset, (row >> scale) as CellOffset);
+                    cam.draw_state(dx + x, dy + y, state);
+                }
+            }
+        }
+    }
+
     match scale {
         // Each leaf is 8x8. At this scale, each screen pixel is exactly 1 cell
         0 => {
-            draw_rule(cam, (cell.nw & !LEAF_MASK) as u16, dx, dy, scale);
-            draw_rule(cam, cell.ne as u16, dx + 4, dy, scale);
-            draw_rule(cam, cell.sw as u16, dx, dy + 4, scale);
-            draw_rule(cam, cell.se as u16, dx + 4, dy + 4, scale);
+            draw_rule(cam, nw, dx, dy, scale);
+            draw_rule(cam, ne, dx + 4, dy, scale);
+            draw_rule(cam, sw, dx, dy + 4, scale);
+            draw_rule(cam, se, dx + 4, dy + 4, scale);
         }
 
         // Each leaf is 4x4. At this scale, each screen pixel is a 2x2 array of cells
         1 => {
-            draw_rule(cam, (cell.nw & !LEAF_MASK) as u16, dx, dy, scale);
-            draw_rule(cam, cell.ne as u16, dx + 2, dy, scale);
-            draw_rule(cam, cell.sw as u16, dx, dy + 2, scale);
-            draw_rule(cam, cell.se as u16, dx + 2, dy + 2, scale);
+            draw_rule(cam, nw, dx, dy, scale);
+            draw_rule(cam, ne, dx + 2, dy, scale);
+            draw_rule(cam, sw, dx, dy + 2, scale);
+            draw_rule(cam, se, dx + 2, dy + 2, scale);
         }
 
         // Each leaf is 2x2. At this scale, each screen pixel is a 4x4 array of cells
         2 => {
-            draw_rule(cam, (cell.nw & !LEAF_MASK) as u16, dx, dy, scale);
-            draw_rule(cam, cell.ne as u16, dx + 1, dy, scale);
-            draw_rule(cam, cell.sw as u16, dx, dy + 1, scale);
-            draw_rule(cam, cell.se as u16, dx + 1, dy + 1, scale);
+            draw_rule(cam, nw, dx, dy, scale);
+            draw_rule(cam, ne, dx + 1, dy, scale);
+            draw_rule(cam, sw, dx, dy + 1, scale);
+            draw_rule(cam, se, dx + 1, dy + 1, scale);
         }
 
         // Each leaf is 1x1
@@ -425,7 +598,7 @@ fn draw_leaf(cam: &mut Camera, cell: Cell, dx: CellOffset, dy: CellOffset, scale
 /// Draw a `2^n` cell. It's important to note here that n >= 3. n = 3 is a leaf
 fn draw_cell(
     cam: &mut Camera,
-    buf: &[Cell],
+    buf: &CellBuf,
     cell: Cell,
     dx: CellOffset,
     dy: CellOffset,
@@ -450,7 +623,7 @@ fn draw_cell(
 
     // Leaf cell
     } else if n == 3 {
-        draw_leaf(cam, cell, dx, dy, scale);
+        draw_leaf(cam, buf, cell, dx, dy, scale);
 
     // Non-leaf cell
     } else {
@@ -463,3 +636,55 @@ fn draw_cell(
         draw_cell(cam, buf, buf[cell.se], dx + sw, dy + sw, n - 1, scale);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::rule_set::RuleSet;
+    use crate::world::Rect;
+    use crate::world::World;
+
+    use super::Camera;
+
+    #[test]
+    fn test_draw_grid() {
+        // A 6 by 4 grid, which spans -3..3 horizontally and -1..3 vertically
+        let mut world = World::new("B3/S23:P6,4".parse().unwrap());
+
+        // Cells in the top left and bottom right corners of the grid
+        world.set(-3, 2);
+        world.set(2, -1);
+
+        let mut cam = Camera::new(4, 2);
+        assert_eq!(cam.viewport(&world), Rect::new(-4, 3, 8, 8));
+
+        cam.draw(&world);
+
+        // The edges lie just outside of the grid, right next to the corner cells
+        insta::assert_snapshot!(cam.render(), @r"
+        ⡟⠉⠉⢹
+        ⠓⠒⠒⠛
+        ");
+    }
+
+    #[test]
+    fn test_draw_states() {
+        // Cells live in state 1, and die through states 2 and 3
+        let mut world = World::new(RuleSet::new(0b100, 0).generations(4));
+
+        world.set_state(-4, 3, 1);
+        world.set_state(-1, 3, 1);
+        world.set_state(-2, 3, 2);
+        world.set_state(0, 3, 3);
+        world.set_state(2, 3, 2);
+        world.set_state(3, 3, 3);
+
+        let mut cam = Camera::new(4, 2);
+        cam.draw(&world);
+
+        // Characters with a live cell are drawn as usual, the others in their lowest state
+        insta::assert_debug_snapshot!(
+            cam.render(),
+            @r#""⠁⠉\u{1b}[35m⠁\u{1b}[0m\u{1b}[34m⠉\u{1b}[0m\n⠀⠀⠀⠀\n""#
+        );
+    }
+}
@@ -1,7 +1,12 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+
 use tracing::debug;
 use tracing::trace;
 
 use crate::camera::Camera;
+use crate::rule_set::MultiState;
+use crate::rule_set::Phases;
 use crate::CellOffset;
 
 /// On 64 bit machines: 1 followed by 63 0s, `9_223_372_036_854_775_808`.
@@ -23,7 +28,7 @@ pub const RES_UNSET_MASK: usize = LEAF_MASK;
 /// A `CellHash` is either an index into a list of `Cell`s, or 4 cell stored directly as a u16
 pub type CellHash = usize;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Cell {
     pub nw: CellHash,
     pub ne: CellHash,
@@ -51,13 +56,14 @@ impl Cell {
         }
     }
 
-    /// Create a new leaf node given 4 rules
-    pub const fn leaf(nw: u16, ne: u16, sw: u16, se: u16) -> Self {
+    /// Create a new leaf node given 4 rules, or 4 blocks of states in buffers of more than 2
+    /// states. See [`CellBuf::block`].
+    pub const fn leaf(nw: usize, ne: usize, sw: usize, se: usize) -> Self {
         Self {
-            nw: nw as usize | LEAF_MASK,
-            ne: ne as usize,
-            sw: sw as usize,
-            se: se as usize,
+            nw: nw | LEAF_MASK,
+            ne,
+            sw,
+            se,
         }
     }
 
@@ -71,7 +77,7 @@ impl Cell {
     }
 
     /// Grow the current cell about its center by a factor of 2
-    pub fn grow(&self, buf: &mut Vec<Cell>) -> Self {
+    pub fn grow(&self, buf: &mut CellBuf) -> Self {
         let mask = if self.is_leaf() { LEAF_MASK } else { 0 };
 
         let nw = Cell {
@@ -102,25 +108,63 @@ impl Cell {
             se: 0,
         };
 
-        let n = buf.len();
-
-        buf.push(nw);
-        buf.push(ne);
-        buf.push(sw);
-        buf.push(se);
-
         Cell {
-            nw: n,
-            ne: n + 1,
-            sw: n + 2,
-            se: n + 3,
+            nw: buf.insert(nw),
+            ne: buf.insert(ne),
+            sw: buf.insert(sw),
+            se: buf.insert(se),
         }
     }
 
     /// For a cell of sidelength `2^k`, this returns a cell of sidelength `2^{k - 1}`, the result
-    /// after `2^{k - 2}` iterations
-    pub fn next(&mut self, next: &[u16], buf: &mut Vec<Cell>) -> usize {
-        self.compute_res(next, buf)
+    /// after `2^{k - 3}` iterations, starting from `phase` of the rule
+    pub fn next(&mut self, next: &Phases, phase: u8, buf: &mut CellBuf) -> usize {
+        self.compute_res(next, phase, buf)
+    }
+
+    /// Like [`Cell::next`], but only advances by `2^j` iterations.
+    ///
+    /// The cell has sidelength `2^depth`, and `j` can be at most `depth - 3`, at which point this
+    /// is the same as [`Cell::next`].
+    pub fn step(&mut self, depth: u8, j: u8, next: &Phases, phase: u8, buf: &mut CellBuf) -> usize {
+        assert!(
+            depth >= 4,
+            "Leaves can only be advanced by a single iteration"
+        );
+        assert!(
+            j + 3 <= depth,
+            "A 2^{depth} cell can be advanced by at most 2^{} iterations, got 2^{j}",
+            depth - 3
+        );
+
+        self.compute_step(depth, j, next, phase, buf)
+    }
+
+    /// Return the 4 rules of a leaf, in `nw`, `ne`, `sw`, `se` order. For leaves of states, see
+    /// [`CellBuf::rules`] instead.
+    pub fn rules(&self) -> Option<[u16; 4]> {
+        if self.is_leaf() {
+            let nw = (self.nw & !LEAF_MASK) as u16;
+
+            Some([nw, self.ne as u16, self.sw as u16, self.se as u16])
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the live cells of a leaf, as `(column, row)` offsets from its top left corner.
+    /// Cells are live in any state but 0.
+    pub fn leaf_cells(&self, buf: &CellBuf) -> impl Iterator<Item = (u8, u8)> {
+        let rules = buf.rules(*self).unwrap_or_default();
+
+        rules
+            .into_iter()
+            .zip([(0, 0), (4, 0), (0, 4), (4, 4)])
+            .flat_map(|(rule, (dx, dy))| {
+                (0..16)
+                    .filter(move |i| rule & (1 << (15 - i)) != 0)
+                    .map(move |i| (dx + i % 4, dy + i / 4))
+            })
     }
 
     pub fn children(&self) -> Option<[usize; 4]> {
@@ -165,7 +209,7 @@ impl Cell {
     fn mask_leaf(&mut self) {
         // We mask leaves so that we have a way to differentiate between non-leaf cells and leaf
         // cells
-        self.nw &= LEAF_MASK;
+        self.nw |= LEAF_MASK;
     }
 
     /// Compute the result of a cell
@@ -174,7 +218,7 @@ impl Cell {
     ///
     /// A rule is just returned as a usize, but a cell is inserted into the buf and its index is
     /// returned
-    fn compute_res(&mut self, next: &[u16], buf: &mut Vec<Cell>) -> usize {
+    fn compute_res(&mut self, next: &Phases, phase: u8, buf: &mut CellBuf) -> usize {
         trace!("Compute res quadrants");
         trace!("nw: {}", self.nw);
         trace!("ne: {}", self.ne);
@@ -188,29 +232,163 @@ impl Cell {
             debug_draw(*self, buf, 0);
 
             // NOTE: We only get here if called from `next`
-            self.compute_leaf_res(next) as usize
-        } else if self.is_16(buf) {
-            debug!("Computing 16 cell res");
-            debug_draw(*self, buf, 1);
+            match next.multi_state() {
+                Some(rule) => self.compute_leaf_states(rule, buf),
+                None => self.compute_leaf_res(next.table(phase)) as usize,
+            }
+        } else if let Some(res) = buf.result(*self, None, phase) {
+            res
+        } else {
+            let key = *self;
+
+            let cell = if self.is_16(buf) {
+                debug!("Computing 16 cell res");
+                debug_draw(*self, buf, 1);
 
-            let cell = self.compute_node_res16(next, buf);
+                self.compute_node_res16(next, phase, buf)
+            } else {
+                debug!("Computing node res");
 
-            let n = buf.len();
-            buf.push(cell);
+                self.compute_node_res(next, phase, buf)
+            };
 
-            n
+            let res = buf.insert(cell);
+            buf.set_result(key, None, phase, res);
+
+            res
+        }
+    }
+
+    /// Compute the result of a `2^depth` cell after `2^j` iterations
+    ///
+    /// Unlike [`Cell::compute_res`], this always returns an index.
+    fn compute_step(
+        &mut self,
+        depth: u8,
+        j: u8,
+        next: &Phases,
+        phase: u8,
+        buf: &mut CellBuf,
+    ) -> usize {
+        if self.is_void() {
+            0
+        } else if j + 3 == depth {
+            // Full speed ahead
+            self.compute_res(next, phase, buf)
+        } else if let Some(res) = buf.result(*self, Some(j), phase) {
+            res
         } else {
-            debug!("Computing node res");
+            let cell = if depth == 4 {
+                debug!("Stepping 16 cell");
+
+                self.compute_node_step16(next, phase, buf)
+            } else {
+                debug!("Stepping node by 2^{j}");
 
-            let cell = self.compute_node_res(next, buf); //
+                self.compute_node_step(depth, j, next, phase, buf)
+            };
 
-            let n = buf.len();
-            buf.push(cell);
+            let res = buf.insert(cell);
+            buf.set_result(*self, Some(j), phase, res);
 
-            n
+            res
         }
     }
 
+    /// Computes the result of a 16 cell after a single iteration
+    /// Returns an 8 cell
+    #[rustfmt::skip]
+    fn compute_node_step16(&self, next: &Phases, phase: u8, buf: &mut CellBuf) -> Cell {
+        // these are leaves
+        let nw = buf[self.nw];
+        let ne = buf[self.ne];
+        let sw = buf[self.sw];
+        let se = buf[self.se];
+
+        // cardinal pseudo-leaves
+        let n = cell_utils::h_center8(nw, ne);
+        let s = cell_utils::h_center8(sw, se);
+        let e = cell_utils::v_center8(ne, se);
+        let w = cell_utils::v_center8(nw, sw);
+
+        // center 8 leaf of 16 cell
+        let c = cell_utils::center16(*self, buf);
+
+        // Unlike `compute_node_res16`, we don't advance these. We just take their centers.
+        let n00 = cell_utils::leaf_to_rule(nw, buf);
+        let n01 = cell_utils::leaf_to_rule(n, buf);
+        let n02 = cell_utils::leaf_to_rule(ne, buf);
+        let n10 = cell_utils::leaf_to_rule(w, buf);
+        let n11 = cell_utils::leaf_to_rule(c, buf);
+        let n12 = cell_utils::leaf_to_rule(e, buf);
+        let n20 = cell_utils::leaf_to_rule(sw, buf);
+        let n21 = cell_utils::leaf_to_rule(s, buf);
+        let n22 = cell_utils::leaf_to_rule(se, buf);
+
+        // n00 n01 n02
+        // n10 n11 n12
+        // n20 n21 n22
+        let mut tl = Cell::leaf(n00, n01, n10, n11);
+        let mut tr = Cell::leaf(n01, n02, n11, n12);
+        let mut bl = Cell::leaf(n10, n11, n20, n21);
+        let mut br = Cell::leaf(n11, n12, n21, n22);
+
+        // The result of a leaf is a rule
+        let tl_res = tl.compute_res(next, phase, buf);
+        let tr_res = tr.compute_res(next, phase, buf);
+        let bl_res = bl.compute_res(next, phase, buf);
+        let br_res = br.compute_res(next, phase, buf);
+
+        Cell::leaf(tl_res, tr_res, bl_res, br_res)
+    }
+
+    /// Computes the result of a `2^depth` cell after `2^j` iterations, for `depth > 4` and
+    /// `j < depth - 3`
+    #[rustfmt::skip]
+    fn compute_node_step(&self, depth: u8, j: u8, next: &Phases, phase: u8, buf: &mut CellBuf) -> Cell {
+        // at least 16 cells
+        let nw = buf[self.nw];
+        let ne = buf[self.ne];
+        let sw = buf[self.sw];
+        let se = buf[self.se];
+
+        // cardinal pseudo-cells
+        let n = cell_utils::h_center(nw, ne);
+        let s = cell_utils::h_center(sw, se);
+        let e = cell_utils::v_center(ne, se);
+        let w = cell_utils::v_center(nw, sw);
+
+        // center n/2 cell of n cell
+        let c = cell_utils::center(*self, buf);
+
+        // Unlike `compute_node_res`, we don't advance these. We just take their centers, which
+        // leaves all `2^j` iterations to the second round.
+        let n00 = cell_utils::centered(nw, depth - 1, buf);
+        let n01 = cell_utils::centered(n,  depth - 1, buf);
+        let n02 = cell_utils::centered(ne, depth - 1, buf);
+        let n10 = cell_utils::centered(w,  depth - 1, buf);
+        let n11 = cell_utils::centered(c,  depth - 1, buf);
+        let n12 = cell_utils::centered(e,  depth - 1, buf);
+        let n20 = cell_utils::centered(sw, depth - 1, buf);
+        let n21 = cell_utils::centered(s,  depth - 1, buf);
+        let n22 = cell_utils::centered(se, depth - 1, buf);
+
+        // n00 n01 n02
+        // n10 n11 n12
+        // n20 n21 n22
+        let mut tl = Cell::new(n00, n01, n10, n11);
+        let mut tr = Cell::new(n01, n02, n11, n12);
+        let mut bl = Cell::new(n10, n11, n20, n21);
+        let mut br = Cell::new(n11, n12, n21, n22);
+
+        let nw = tl.compute_step(depth - 1, j, next, phase, buf);
+        let ne = tr.compute_step(depth - 1, j, next, phase, buf);
+        let sw = bl.compute_step(depth - 1, j, next, phase, buf);
+        let se = br.compute_step(depth - 1, j, next, phase, buf);
+
+        Cell::new(nw, ne, sw, se)
+    }
+
     /// For a leaf cell, this computes its result.
     /// Remember that a leaf cell is composed entirely of u16s, each 4 squares on a side. This
     /// makes leaves 8 cells, and their result 4 cells.
@@ -289,10 +467,26 @@ impl Cell {
         rule
     }
 
+    /// Like [`Cell::compute_leaf_res`], but for the leaves of rules of more than 2 states, which hold
+    /// blocks of states. Returns the index of the resulting block.
+    fn compute_leaf_states(&self, rule: &MultiState, buf: &mut CellBuf) -> usize {
+        let states = buf.leaf_states(*self);
+
+        let block = std::array::from_fn(|i| {
+            let (row, col) = (2 + i / 4, 2 + i % 4);
+
+            rule.next(std::array::from_fn(|j| {
+                states[row + j / 3 - 1][col + j % 3 - 1]
+            }))
+        });
+
+        buf.insert_block(block)
+    }
+
     /// Computes the result of a 16 cell
     /// Returns an 8 cell
     #[rustfmt::skip]
-    fn compute_node_res16(&self, next: &[u16], buf: &mut Vec<Cell>) -> Cell {
+    fn compute_node_res16(&self, next: &Phases, phase: u8, buf: &mut CellBuf) -> Cell {
         // these are leaves
         let mut nw = buf[self.nw];
         let mut ne = buf[self.ne];
@@ -313,17 +507,17 @@ impl Cell {
         // center 8 leaf of 16 cell
         let mut c = cell_utils::center16(*self, buf);
 
-        // NOTE: This downcast is safe. The only way down from here is either void or leaf
+        // The only way down from here is either void or leaf
         // All of these are rules
-        let n00 = nw.compute_res(next, buf) as u16;
-        let n01 =  n.compute_res(next, buf) as u16;
-        let n02 = ne.compute_res(next, buf) as u16;
-        let n10 =  w.compute_res(next, buf) as u16;
-        let n11 =  c.compute_res(next, buf) as u16;
-        let n12 =  e.compute_res(next, buf) as u16;
-        let n20 = sw.compute_res(next, buf) as u16;
-        let n21 =  s.compute_res(next, buf) as u16;
-        let n22 = se.compute_res(next, buf) as u16;
+        let n00 = nw.compute_res(next, phase, buf);
+        let n01 =  n.compute_res(next, phase, buf);
+        let n02 = ne.compute_res(next, phase, buf);
+        let n10 =  w.compute_res(next, phase, buf);
+        let n11 =  c.compute_res(next, phase, buf);
+        let n12 =  e.compute_res(next, phase, buf);
+        let n20 = sw.compute_res(next, phase, buf);
+        let n21 =  s.compute_res(next, phase, buf);
+        let n22 = se.compute_res(next, phase, buf);
 
         // n00 n01 n02
         // n10 n11 n12
@@ -333,18 +527,21 @@ impl Cell {
         let mut bl = Cell::leaf(n10, n11, n20, n21);
         let mut br = Cell::leaf(n11, n12, n21, n22);
 
-        // NOTE: This downcast is safe for the same reason as the one above
-        let tl_res = tl.compute_res(next, buf) as u16;
-        let tr_res = tr.compute_res(next, buf) as u16;
-        let bl_res = bl.compute_res(next, buf) as u16;
-        let br_res = br.compute_res(next, buf) as u16;
+        // The second iteration may use a different rule
+        let phase = next.advance(phase, 0);
+
+        // These are rules for the same reason as the ones above
+        let tl_res = tl.compute_res(next, phase, buf);
+        let tr_res = tr.compute_res(next, phase, buf);
+        let bl_res = bl.compute_res(next, phase, buf);
+        let br_res = br.compute_res(next, phase, buf);
 
         Cell::leaf(tl_res, tr_res, bl_res, br_res)
     }
 
     /// Computes the result of a 2^k cell for k > 4 (i.e. at least 32 cells)
     #[rustfmt::skip]
-    fn compute_node_res(&mut self, next: &[u16], buf: &mut Vec<Cell>) -> Cell {
+    fn compute_node_res(&mut self, next: &Phases, phase: u8, buf: &mut CellBuf) -> Cell {
         // at least 16 cells
         let mut nw = buf[self.nw];
         let mut ne = buf[self.ne];
@@ -377,39 +574,39 @@ impl Cell {
         let mut c = cell_utils::center(*self, buf);
 
         // All of these are cells
-        let n00 = nw.compute_res(next, buf);
+        let n00 = nw.compute_res(next, phase, buf);
         debug!("n00");
         debug_draw(buf[n00], buf, 1);
 
-        let n01 =  n.compute_res(next, buf);
+        let n01 =  n.compute_res(next, phase, buf);
         debug!("n01");
         debug_draw(buf[n01], buf, 1);
 
-        let n02 = ne.compute_res(next, buf);
+        let n02 = ne.compute_res(next, phase, buf);
         debug!("n02");
         debug_draw(buf[n02], buf, 1);
 
-        let n10 =  w.compute_res(next, buf);
+        let n10 =  w.compute_res(next, phase, buf);
         debug!("n10");
         debug_draw(buf[n10], buf, 1);
 
-        let n11 =  c.compute_res(next, buf);
+        let n11 =  c.compute_res(next, phase, buf);
         debug!("n11");
         debug_draw(buf[n11], buf, 1);
 
-        let n12 =  e.compute_res(next, buf);
+        let n12 =  e.compute_res(next, phase, buf);
         debug!("n12");
         debug_draw(buf[n12], buf, 1);
 
-        let n20 = sw.compute_res(next, buf);
+        let n20 = sw.compute_res(next, phase, buf);
         debug!("n20");
         debug_draw(buf[n20], buf, 1);
 
-        let n21 =  s.compute_res(next, buf);
+        let n21 =  s.compute_res(next, phase, buf);
         debug!("n21");
         debug_draw(buf[n21], buf, 1);
 
-        let n22 = se.compute_res(next, buf);
+        let n22 = se.compute_res(next, phase, buf);
         debug!("n22");
         debug_draw(buf[n22], buf, 1);
 
@@ -433,10 +630,13 @@ impl Cell {
         debug!("br:");
         debug_draw(br, buf, 1);
 
-        let nw = tl.compute_res(next, buf);
-        let ne = tr.compute_res(next, buf);
-        let sw = bl.compute_res(next, buf);
-        let se = br.compute_res(next, buf);
+        // The second half starts at least 2 iterations later, see `Phases::advance`
+        let phase = next.advance(phase, 1);
+
+        let nw = tl.compute_res(next, phase, buf);
+        let ne = tr.compute_res(next, phase, buf);
+        let sw = bl.compute_res(next, phase, buf);
+        let se = br.compute_res(next, phase, buf);
 
         debug!("tl res:");
         debug_draw(buf[nw], buf, 0);
@@ -499,6 +699,252 @@ impl Cell {
     }
 }
 
+/// The memory of a [`World`], where every [`Cell`] lives.
+///
+/// Cells are hash-consed: inserting a cell that is already in the buffer just returns the index of
+/// the existing copy. Indices are canonical as a result, two cells are equal if and only if they
+/// live at the same index. This is also what allows us to memoize results.
+///
+/// The first cell is always the canonical void cell.
+///
+/// Cells of Generations rules can be in more than 2 states, which doesn't fit the bits of a rule.
+/// Instead, the leaves of those buffers hold the indices of 4x4 blocks of states, which are
+/// hash-consed as well. The first block is always the empty one, so empty leaves are still void.
+///
+/// [`World`]: crate::world::World
+pub struct CellBuf {
+    cells: Vec<Cell>,
+
+    /// Index of every cell in `cells`
+    ids: HashMap<Cell, usize>,
+
+    /// Memoized results, by cell, by the number of iterations as a power of 2 and by the phase of
+    /// the rule they start from. Full results, like the ones computed by [`Cell::next`], have no
+    /// number of iterations.
+    results: HashMap<(Cell, Option<u8>, u8), usize>,
+
+    /// Number of states a cell can be in
+    states: u16,
+
+    /// Blocks of states, only used with more than 2 states. See [`CellBuf::block`].
+    blocks: Vec<[u8; 16]>,
+
+    /// Index of every block in `blocks`
+    block_ids: HashMap<[u8; 16], usize>,
+}
+
+impl Default for CellBuf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CellBuf {
+    pub fn new() -> Self {
+        Self::with_states(2)
+    }
+
+    /// Create a buffer for the cells of a rule with `states` states
+    pub fn with_states(states: u16) -> Self {
+        Self {
+            cells: vec![Cell::void()],
+            ids: HashMap::from([(Cell::void(), 0)]),
+            results: HashMap::new(),
+            states,
+            blocks: vec![[0; 16]],
+            block_ids: HashMap::from([([0; 16], 0)]),
+        }
+    }
+
+    /// Number of states a cell can be in
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    /// The states of the 4x4 block `rule`, row by row from the top left, where `rule` is one of
+    /// the 4 quadrants of a leaf. With 2 states, this is just the bits of the rule.
+    pub fn block(&self, rule: usize) -> [u8; 16] {
+        if self.states > 2 {
+            self.blocks[rule]
+        } else {
+            std::array::from_fn(|i| (rule >> (15 - i)) as u8 & 1)
+        }
+    }
+
+    /// Insert a 4x4 block of states and return it as the quadrant of a leaf. This is the inverse
+    /// of [`CellBuf::block`].
+    pub fn insert_block(&mut self, block: [u8; 16]) -> usize {
+        if self.states <= 2 {
+            return block
+                .iter()
+                .fold(0, |acc, &state| acc << 1 | (state != 0) as usize);
+        }
+
+        if let Some(&i) = self.block_ids.get(&block) {
+            return i;
+        }
+
+        let n = self.blocks.len();
+
+        self.blocks.push(block);
+        self.block_ids.insert(block, n);
+
+        n
+    }
+
+    /// The states of a leaf, by row from the top left. Void is all dead.
+    pub fn leaf_states(&self, leaf: Cell) -> [[u8; 8]; 8] {
+        let blocks = [leaf.nw & !LEAF_MASK, leaf.ne, leaf.sw, leaf.se].map(|b| self.block(b));
+
+        std::array::from_fn(|row| {
+            std::array::from_fn(|col| blocks[row / 4 * 2 + col / 4][row % 4 * 4 + col % 4])
+        })
+    }
+
+    /// Insert a leaf of states, by row from the top left, and return its index. This is the
+    /// inverse of [`CellBuf::leaf_states`].
+    pub fn insert_leaf(&mut self, states: [[u8; 8]; 8]) -> usize {
+        let [nw, ne, sw, se] = [(0, 0), (0, 4), (4, 0), (4, 4)].map(|(row, col)| {
+            self.insert_block(std::array::from_fn(|i| states[row + i / 4][col + i % 4]))
+        });
+
+        self.insert(Cell::leaf(nw, ne, sw, se))
+    }
+
+    /// Like [`Cell::rules`], but a cell is on in any state but 0
+    pub fn rules(&self, leaf: Cell) -> Option<[u16; 4]> {
+        let rules = leaf.rules()?;
+
+        if self.states <= 2 {
+            return Some(rules);
+        }
+
+        let [nw, ne, sw, se] = [leaf.nw & !LEAF_MASK, leaf.ne, leaf.sw, leaf.se].map(|b| {
+            self.blocks[b]
+                .iter()
+                .fold(0, |acc, &state| acc << 1 | (state != 0) as u16)
+        });
+
+        Some([nw, ne, sw, se])
+    }
+
+    /// Insert a cell and return its index. If an equal cell is already in the buffer, its index
+    /// is returned instead.
+    pub fn insert(&mut self, cell: Cell) -> usize {
+        // An empty leaf is just void
+        if cell == Cell::leaf_uninit() {
+            return 0;
+        }
+
+        if let Some(&i) = self.ids.get(&cell) {
+            return i;
+        }
+
+        let n = self.cells.len();
+
+        self.cells.push(cell);
+        self.ids.insert(cell, n);
+
+        n
+    }
+
+    /// Index of `cell`, if it is in the buffer
+    pub fn index_of(&self, cell: &Cell) -> Option<usize> {
+        self.ids.get(cell).copied()
+    }
+
+    /// The memoized result of `cell` after `2^j` iterations from `phase`, or its full result if
+    /// `j` is `None`
+    pub fn result(&self, cell: Cell, j: Option<u8>, phase: u8) -> Option<usize> {
+        self.results.get(&(cell, j, phase)).copied()
+    }
+
+    pub fn set_result(&mut self, cell: Cell, j: Option<u8>, phase: u8, res: usize) {
+        self.results.insert((cell, j, phase), res);
+    }
+
+    /// Iterate over every memoized result
+    pub fn results(&self) -> impl Iterator<Item = (Cell, Option<u8>, u8, usize)> {
+        self.results
+            .iter()
+            .map(|(&(cell, j, phase), &res)| (cell, j, phase, res))
+    }
+
+    /// Copy the cells reachable from `roots` into a new buffer, along with the memoized results
+    /// that only involve those cells. Returns the new buffer and the new indices of `roots`.
+    ///
+    /// Cells are copied children first, so every cell comes after its children in the new buffer.
+    pub fn compact(&self, roots: &[usize]) -> (CellBuf, Vec<usize>) {
+        let mut buf = CellBuf::with_states(self.states);
+        let mut map = HashMap::from([(0, 0)]);
+
+        let roots = roots
+            .iter()
+            .map(|&ptr| self.copy_into(ptr, &mut buf, &mut map))
+            .collect();
+
+        for (&(cell, j, phase), &res) in &self.results {
+            let Some(&res) = map.get(&res) else {
+                continue;
+            };
+
+            let children = [cell.nw, cell.ne, cell.sw, cell.se].map(|ptr| map.get(&ptr).copied());
+
+            if let [Some(nw), Some(ne), Some(sw), Some(se)] = children {
+                buf.set_result(Cell::new(nw, ne, sw, se), j, phase, res);
+            }
+        }
+
+        (buf, roots)
+    }
+
+    /// Copy the cell `ptr` and the cells reachable from it into `buf`, and return its index there.
+    /// `map` holds the indices of the cells copied so far, starting with void.
+    pub fn copy_into(
+        &self,
+        ptr: usize,
+        buf: &mut CellBuf,
+        map: &mut HashMap<usize, usize>,
+    ) -> usize {
+        if let Some(&n) = map.get(&ptr) {
+            return n;
+        }
+
+        let cell = self.cells[ptr];
+
+        let n = match cell.children() {
+            Some([nw, ne, sw, se]) => {
+                let nw = self.copy_into(nw, buf, map);
+                let ne = self.copy_into(ne, buf, map);
+                let sw = self.copy_into(sw, buf, map);
+                let se = self.copy_into(se, buf, map);
+
+                buf.insert(Cell::new(nw, ne, sw, se))
+            }
+            // Blocks of states are copied over along with their leaves
+            None if self.states > 2 => {
+                let [nw, ne, sw, se] = [cell.nw & !LEAF_MASK, cell.ne, cell.sw, cell.se]
+                    .map(|b| buf.insert_block(self.blocks[b]));
+
+                buf.insert(Cell::leaf(nw, ne, sw, se))
+            }
+            None => buf.insert(cell),
+        };
+
+        map.insert(ptr, n);
+
+        n
+    }
+}
+
+impl Deref for CellBuf {
+    type Target = [Cell];
+
+    fn deref(&self) -> &Self::Target {
+        &self.cells
+    }
+}
+
 impl std::fmt::Debug for Cell {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.is_leaf() {
@@ -526,6 +972,7 @@ impl std::fmt::Debug for Cell {
 
 mod cell_utils {
     use crate::cell::Cell;
+    use crate::cell::CellBuf;
     use crate::cell::LEAF_MASK;
 
     use tracing::trace;
@@ -537,7 +984,46 @@ mod cell_utils {
         let sw = (rule & 0b0000_0000_1100_1100) << 6;
         let se = (rule & 0b0000_0000_0011_0011) << 10;
 
-        Cell::leaf(nw, ne, sw, se)
+        Cell::leaf(nw as usize, ne as usize, sw as usize, se as usize)
+    }
+
+    /// Takes as input a leaf and returns the rule at its center. This is the inverse of
+    /// [`rule_to_leaf`].
+    ///
+    /// In buffers of more than 2 states, this is the block at its center instead.
+    pub fn leaf_to_rule(leaf: Cell, buf: &mut CellBuf) -> usize {
+        if buf.states() > 2 {
+            let states = buf.leaf_states(leaf);
+
+            return buf.insert_block(std::array::from_fn(|i| states[2 + i / 4][2 + i % 4]));
+        }
+
+        let Some([nw, ne, sw, se]) = leaf.rules() else {
+            // Void
+            return 0;
+        };
+
+        let rule = ((nw & 0b0000_0000_0011_0011) << 10)
+            | ((ne & 0b0000_0000_1100_1100) << 6)
+            | ((sw & 0b0011_0011_0000_0000) >> 6)
+            | ((se & 0b1100_1100_0000_0000) >> 10);
+
+        rule as usize
+    }
+
+    /// Given a `2^depth` cell, returns the index of the `2^{depth - 1}` cell at its center
+    pub fn centered(cell: Cell, depth: u8, buf: &mut CellBuf) -> usize {
+        if cell.is_void() {
+            return 0;
+        }
+
+        let center = if depth == 4 {
+            center16(cell, buf)
+        } else {
+            center(cell, buf)
+        };
+
+        buf.insert(center)
     }
 
     /// Given two cells `w` and `e`, returns the cell at their center.
@@ -576,17 +1062,12 @@ mod cell_utils {
         trace!("w: {w:?}");
         trace!("e: {e:?}");
 
-        let nw = w.ne as u16;
-        let ne = (e.nw & !LEAF_MASK) as u16;
-        let sw = w.se as u16;
-        let se = e.sw as u16;
+        let nw = w.ne;
+        let ne = e.nw & !LEAF_MASK;
+        let sw = w.se;
+        let se = e.sw;
 
-        Cell {
-            nw: nw as usize | LEAF_MASK,
-            ne: ne as usize,
-            sw: sw as usize,
-            se: se as usize,
-        }
+        Cell::leaf(nw, ne, sw, se)
     }
 
     /// Given two 8 cells `n` and `s`, returns the leaf at their center.
@@ -594,22 +1075,17 @@ mod cell_utils {
         trace!("n: {n:?}");
         trace!("s: {s:?}");
 
-        let nw = n.sw as u16;
-        let ne = n.se as u16;
-        let sw = (s.nw & !LEAF_MASK) as u16;
-        let se = s.ne as u16;
+        let nw = n.sw;
+        let ne = n.se;
+        let sw = s.nw & !LEAF_MASK;
+        let se = s.ne;
 
         trace!("nw: {nw:016b}");
         trace!("ne: {ne:016b}");
         trace!("sw: {sw:016b}");
         trace!("se: {se:016b}");
 
-        Cell {
-            nw: nw as usize | LEAF_MASK,
-            ne: ne as usize,
-            sw: sw as usize,
-            se: se as usize,
-        }
+        Cell::leaf(nw, ne, sw, se)
     }
 
     /// On a 16 cell, this is its 8x8 center leaf
@@ -628,22 +1104,17 @@ mod cell_utils {
         trace!("se: {se:?}");
 
         // These are rules, since the cell is not a grandparent
-        let nw = nw.se as u16;
-        let ne = ne.sw as u16;
-        let sw = sw.ne as u16;
-        let se = (se.nw & !LEAF_MASK) as u16;
+        let nw = nw.se;
+        let ne = ne.sw;
+        let sw = sw.ne;
+        let se = se.nw & !LEAF_MASK;
 
         trace!("nw: {nw:016b}");
         trace!("ne: {ne:016b}");
         trace!("sw: {sw:016b}");
         trace!("se: {se:016b}");
 
-        Cell {
-            nw: nw as usize | LEAF_MASK,
-            ne: ne as usize,
-            sw: sw as usize,
-            se: se as usize,
-        }
+        Cell::leaf(nw, ne, sw, se)
     }
 }
 
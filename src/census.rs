@@ -0,0 +1,475 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::WorldOffset;
+use crate::rule_set::RuleSet;
+use crate::world::Periodicity;
+use crate::world::World;
+
+/// The longest period objects are checked for. Anything that doesn't repeat within this many
+/// generations is [`ObjectKind::Unknown`].
+pub const MAX_PERIOD: u64 = 1 << 10;
+
+/// A live cell, along with its state
+pub type StateCell = (WorldOffset, WorldOffset, u8);
+
+/// Digits of the extended Wechsler format, one per column of a 5 cell strip
+const WECHSLER_DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    StillLife,
+    Oscillator,
+    Spaceship,
+    /// Didn't repeat within [`MAX_PERIOD`] generations
+    Unknown,
+}
+
+/// A group of live cells which evolves on its own, see [`separate`]
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub cells: Vec<StateCell>,
+
+    pub periodicity: Option<Periodicity>,
+
+    /// The apgcode of the object, like `xs4_33` for a block, see [`apgcode`]
+    pub code: String,
+}
+
+impl Object {
+    pub fn kind(&self) -> ObjectKind {
+        match self.periodicity {
+            None => ObjectKind::Unknown,
+            Some(p) if p.is_spaceship() => ObjectKind::Spaceship,
+            Some(p) if p.is_oscillator() => ObjectKind::Oscillator,
+            Some(_) => ObjectKind::StillLife,
+        }
+    }
+}
+
+/// Count the objects of `world` by their apgcode, see [`separate`]
+pub fn census(world: &World) -> BTreeMap<String, usize> {
+    let mut census = BTreeMap::new();
+
+    for object in separate(world) {
+        *census.entry(object.code).or_insert(0) += 1;
+    }
+
+    census
+}
+
+/// Split the live cells of a stabilised `world` into objects, and classify each of them.
+///
+/// Cells start out grouped into islands, whose cells touch each other orthogonally or
+/// diagonally. Some objects are made of several islands, like the pulsar, so islands close
+/// enough to share a neighbour are run together and apart. Unless they evolve the same either
+/// way, they are merged into one object. Islands which do evolve the same, like the two blocks
+/// of a bi-block, are kept as separate pseudo-objects.
+///
+/// Only islands that are close at the current generation are checked, and only two groups of
+/// islands at a time.
+///
+/// Cells in any state but 0 are live. Worlds with a live background, see
+/// [`World::background`], aren't supported.
+pub fn separate(world: &World) -> Vec<Object> {
+    let cells: Vec<StateCell> = world
+        .live_cells()
+        .map(|(x, y)| (x, y, world.state(x, y)))
+        .collect();
+
+    let index: HashMap<_, _> = cells
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y, _))| ((x, y), i))
+        .collect();
+
+    // The cells within `r` cells of cell `i`, besides itself
+    let around = |i: usize, r: WorldOffset| {
+        let (x, y, _) = cells[i];
+
+        (-r..=r)
+            .flat_map(move |dx| (-r..=r).map(move |dy| (x + dx, y + dy)))
+            .filter_map(|p| index.get(&p).copied())
+            .filter(move |&j| j != i)
+    };
+
+    // Flood fill the islands
+    let mut islands = vec![usize::MAX; cells.len()];
+    let mut count = 0;
+
+    for start in 0..cells.len() {
+        if islands[start] != usize::MAX {
+            continue;
+        }
+
+        islands[start] = count;
+        let mut stack = vec![start];
+
+        while let Some(i) = stack.pop() {
+            for j in around(i, 1) {
+                if islands[j] == usize::MAX {
+                    islands[j] = count;
+                    stack.push(j);
+                }
+            }
+        }
+
+        count += 1;
+    }
+
+    // Islands that share a neighbour may interact
+    let mut pairs: Vec<_> = (0..cells.len())
+        .flat_map(|i| around(i, 2).map(move |j| (i, j)))
+        .map(|(i, j)| (islands[i], islands[j]))
+        .filter(|(a, b)| a < b)
+        .collect();
+    pairs.sort();
+    pairs.dedup();
+
+    let mut groups: Vec<usize> = (0..count).collect();
+    let find = |groups: &mut Vec<usize>, mut a: usize| {
+        while groups[a] != a {
+            groups[a] = groups[groups[a]];
+            a = groups[a];
+        }
+
+        a
+    };
+
+    let rule = world.rule();
+    let group_cells = |groups: &mut Vec<usize>, group: usize| -> Vec<StateCell> {
+        (0..cells.len())
+            .filter(|&i| find(groups, islands[i]) == group)
+            .map(|i| cells[i])
+            .collect()
+    };
+
+    for (a, b) in pairs {
+        let (a, b) = (find(&mut groups, a), find(&mut groups, b));
+
+        if a == b {
+            continue;
+        }
+
+        let (cells_a, cells_b) = (group_cells(&mut groups, a), group_cells(&mut groups, b));
+
+        if !evolve_apart(rule, &cells_a, &cells_b) {
+            groups[b] = a;
+        }
+    }
+
+    let mut objects: BTreeMap<usize, Vec<StateCell>> = BTreeMap::new();
+    for i in 0..cells.len() {
+        let group = find(&mut groups, islands[i]);
+
+        objects.entry(group).or_default().push(cells[i]);
+    }
+
+    objects
+        .into_values()
+        .map(|cells| {
+            let world = world_with(rule, &cells);
+            let periodicity = world.detect_period(MAX_PERIOD);
+            let code = apgcode(&world, periodicity);
+
+            Object {
+                cells,
+                periodicity,
+                code,
+            }
+        })
+        .collect()
+}
+
+/// Check whether `a` and `b` evolve the same together as they do apart, for two periods of the
+/// pattern they make together. Both also need to repeat on their own, or they are only parts
+/// of an object in the making, like a spark that doesn't change what comes next.
+fn evolve_apart(rule: &RuleSet, a: &[StateCell], b: &[StateCell]) -> bool {
+    let both = [a, b].concat();
+
+    let mut worlds = [
+        world_with(rule, a),
+        world_with(rule, b),
+        world_with(rule, &both),
+    ];
+
+    let states = |world: &World| {
+        let mut cells: Vec<StateCell> = world
+            .live_cells()
+            .map(|(x, y)| (x, y, world.state(x, y)))
+            .collect();
+        cells.sort();
+
+        cells
+    };
+
+    let step = |worlds: &mut [World; 3]| {
+        for world in worlds.iter_mut() {
+            world.step(1);
+        }
+
+        let mut apart = [states(&worlds[0]), states(&worlds[1])].concat();
+        apart.sort();
+
+        apart == states(&worlds[2])
+    };
+
+    let start = [states(&worlds[0]), states(&worlds[1])];
+
+    // Most islands that interact do so right away, which is cheaper to check than the period
+    if !step(&mut worlds) {
+        return false;
+    }
+
+    let Some(periodicity) = worlds[2].detect_period(MAX_PERIOD) else {
+        return false;
+    };
+
+    for _ in 1..2 * periodicity.period {
+        if !step(&mut worlds) {
+            return false;
+        }
+    }
+
+    let (dx, dy) = (2 * periodicity.dx, 2 * periodicity.dy);
+
+    start.iter().zip(&worlds).all(|(start, world)| {
+        let moved: Vec<_> = start.iter().map(|&(x, y, s)| (x + dx, y + dy, s)).collect();
+
+        moved == states(world)
+    })
+}
+
+/// A new world of `rule` holding `cells`
+pub(crate) fn world_with(rule: &RuleSet, cells: &[StateCell]) -> World {
+    let mut world = World::new(rule.clone());
+
+    let reach = cells
+        .iter()
+        .map(|&(x, y, _)| x.abs().max(y.abs()) + 1)
+        .max()
+        .unwrap_or_default();
+
+    while 1 << (world.depth - 1) < reach {
+        world.grow(1);
+    }
+
+    for &(x, y, state) in cells {
+        world.set_state(x, y, state);
+    }
+
+    world
+}
+
+/// Live cells of a picture, with `O` for live cells, whose top left cell is (`x`, `y`)
+#[cfg(test)]
+pub(crate) fn picture(x: WorldOffset, y: WorldOffset, rows: &[&str]) -> Vec<StateCell> {
+    rows.iter()
+        .enumerate()
+        .flat_map(|(dy, row)| {
+            row.bytes()
+                .enumerate()
+                .filter(|&(_, b)| b == b'O')
+                .map(move |(dx, _)| (x + dx as WorldOffset, y - dy as WorldOffset, 1))
+        })
+        .collect()
+}
+
+/// The apgcode of the single object in `world`, given how it repeats.
+///
+/// This is `xs<population>_` for still lifes, `xp<period>_` for oscillators and `xq<period>_`
+/// for spaceships, followed by the extended Wechsler code of the object. Every orientation of
+/// every phase is encoded, and the shortest code wins, then the first one in lexicographical
+/// order. Objects that don't repeat are `PATHOLOGICAL`, and objects that are still settling
+/// down are encoded once they repeat.
+///
+/// See: <https://conwaylife.com/wiki/Apgcode>
+pub fn apgcode(world: &World, periodicity: Option<Periodicity>) -> String {
+    let Some(periodicity) = periodicity else {
+        return "PATHOLOGICAL".to_string();
+    };
+
+    let (buf, roots) = world.buf.compact(&[world.root]);
+    let mut world = World::from_parts(
+        world.rule().clone(),
+        buf,
+        roots[0],
+        world.depth,
+        world.generation(),
+    );
+
+    // Only the phases the object repeats count
+    world.step(periodicity.start);
+
+    let prefix = if periodicity.is_spaceship() {
+        format!("xq{}", periodicity.period)
+    } else if periodicity.is_oscillator() {
+        format!("xp{}", periodicity.period)
+    } else {
+        format!("xs{}", world.live_cells().count())
+    };
+
+    let mut best: Option<String> = None;
+
+    for _ in 0..periodicity.period {
+        let cells: Vec<_> = world.live_cells().collect();
+
+        for code in (0..8).map(|t| wechsler(&cells, t)) {
+            let shorter = |best: &String| (code.len(), &code) < (best.len(), best);
+
+            if best.as_ref().is_none_or(shorter) {
+                best = Some(code);
+            }
+        }
+
+        world.step(1);
+    }
+
+    format!("{prefix}_{}", best.unwrap_or_default())
+}
+
+/// The extended Wechsler code of `cells`, under the `t`th symmetry of the square
+fn wechsler(cells: &[(WorldOffset, WorldOffset)], t: u8) -> String {
+    // Columns to the east and rows to the south
+    let cells: Vec<_> = cells
+        .iter()
+        .map(|&(x, y)| {
+            let (x, y) = if t & 4 != 0 { (-y, -x) } else { (x, -y) };
+            let x = if t & 1 != 0 { -x } else { x };
+            let y = if t & 2 != 0 { -y } else { y };
+
+            (x, y)
+        })
+        .collect();
+
+    let (Some(x0), Some(y0)) = (
+        cells.iter().map(|c| c.0).min(),
+        cells.iter().map(|c| c.1).min(),
+    ) else {
+        return String::new();
+    };
+
+    let width = cells.iter().map(|c| c.0 - x0 + 1).max().unwrap_or_default() as usize;
+    let height = cells.iter().map(|c| c.1 - y0 + 1).max().unwrap_or_default() as usize;
+
+    // Each column of a strip of 5 rows, with the top row as the least significant bit
+    let mut strips = vec![vec![0u8; width]; height.div_ceil(5)];
+    for (x, y) in cells {
+        let (x, y) = ((x - x0) as usize, (y - y0) as usize);
+
+        strips[y / 5][x] |= 1 << (y % 5);
+    }
+
+    let mut code = String::new();
+
+    for (i, strip) in strips.iter().enumerate() {
+        if i > 0 {
+            code.push('z');
+        }
+
+        let len = strip.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+
+        let mut zeros = 0;
+        for &column in &strip[..len] {
+            if column == 0 {
+                zeros += 1;
+                continue;
+            }
+
+            push_zeros(&mut code, zeros);
+            zeros = 0;
+
+            code.push(WECHSLER_DIGITS[column as usize] as char);
+        }
+    }
+
+    code
+}
+
+/// Write a run of `n` empty columns: `0`, `w` and `x` stand for 1, 2 and 3 of them, and `y`
+/// followed by a digit for 4 to 39
+fn push_zeros(code: &mut String, mut n: usize) {
+    while n >= 4 {
+        let run = n.min(39);
+
+        code.push('y');
+        code.push(WECHSLER_DIGITS[run - 4] as char);
+
+        n -= run;
+    }
+
+    match n {
+        1 => code.push('0'),
+        2 => code.push('w'),
+        3 => code.push('x'),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rule_set::B3S23;
+
+    use super::ObjectKind;
+    use super::picture;
+    use super::world_with;
+
+    #[test]
+    fn test_census() {
+        let cells = [
+            picture(-20, 20, &["OO", "OO"]),
+            picture(0, 20, &["OOO"]),
+            picture(20, 20, &[".O.", "..O", "OOO"]),
+            picture(-20, 0, &[".OO.", "O..O", ".OO."]),
+            picture(0, 0, &["OO.", "O.O", ".O."]),
+            picture(20, 0, &[".O..O", "O....", "O...O", "OOOO."]),
+            // A bi-block is a pseudo still life, made of two blocks
+            picture(-20, -20, &["OO.OO", "OO.OO"]),
+        ]
+        .concat();
+
+        insta::assert_debug_snapshot!(super::census(&world_with(&B3S23, &cells)), @r#"
+        {
+            "xp2_7": 1,
+            "xq4_153": 1,
+            "xq4_6frc": 1,
+            "xs4_33": 3,
+            "xs5_253": 1,
+            "xs6_696": 1,
+        }
+        "#);
+    }
+
+    #[test]
+    fn test_separate_pulsar() {
+        // The pulsar is made of 12 islands, which don't last on their own
+        let cells = picture(
+            -6,
+            6,
+            &[
+                "..OOO...OOO..",
+                ".............",
+                "O....O.O....O",
+                "O....O.O....O",
+                "O....O.O....O",
+                "..OOO...OOO..",
+                ".............",
+                "..OOO...OOO..",
+                "O....O.O....O",
+                "O....O.O....O",
+                "O....O.O....O",
+                ".............",
+                "..OOO...OOO..",
+            ],
+        );
+
+        let objects = super::separate(&world_with(&B3S23, &cells));
+
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].kind(), ObjectKind::Oscillator);
+        assert_eq!(
+            objects[0].code,
+            "xp3_co9nas0san9oczgoldlo0oldlogz1047210127401"
+        );
+    }
+}
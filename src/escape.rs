@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::WorldOffset;
+use crate::census::world_with;
+use crate::rule_set::RuleSet;
+use crate::world::Rect;
+use crate::world::World;
+
+/// How close live cells have to be to belong to the same object. Parts of a spaceship may lie
+/// two cells apart, like the tail of the LWSS.
+const REACH: WorldOffset = 2;
+
+/// Room left between spaceships while checking they fly in a rule
+const SPACING: WorldOffset = 64;
+
+/// The known spaceships of Life, as their apgcode, their period, a phase drawn with `O` in rows
+/// from north to south, and how far it moves to the east and to the north in a period
+#[rustfmt::skip]
+const SHIPS: [(&str, u64, &str, WorldOffset, WorldOffset); 4] = [
+    ("xq4_153", 4, ".O.\n..O\nOOO", 1, -1),
+    ("xq4_6frc", 4, ".O..O\nO....\nO...O\nOOOO.", -2, 0),
+    ("xq4_27dee6", 4, "...O..\n.O...O\nO.....\nO....O\nOOOOO.", -2, 0),
+    ("xq4_27deee6", 4, "...OO..\n.O....O\nO......\nO.....O\nOOOOOO.", -2, 0),
+];
+
+/// A known spaceship flying away from every other live cell, which it never meets again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscapingShip {
+    /// See [`crate::census::apgcode`]
+    pub code: &'static str,
+
+    pub cells: Vec<(WorldOffset, WorldOffset)>,
+
+    /// How far the ship moves to the east and to the north every `period` generations
+    pub dx: WorldOffset,
+    pub dy: WorldOffset,
+    pub period: u64,
+}
+
+/// The known spaceships which fly in a rule, by every phase of theirs in every orientation
+pub(crate) struct Ships {
+    /// Keyed by the cells of the phase, relative to the south west corner of its bounding box,
+    /// and without any cells of their own
+    phases: HashMap<Vec<(WorldOffset, WorldOffset)>, EscapingShip>,
+
+    /// Number of live cells of the largest phase
+    max_len: usize,
+}
+
+impl Ships {
+    /// Look for the known spaceships which fly in `rule`, by running them side by side
+    pub(crate) fn new(rule: &RuleSet) -> Self {
+        let ships: Vec<_> = SHIPS
+            .iter()
+            .enumerate()
+            .map(|(i, &(code, period, drawing, dx, dy))| {
+                let x0 = i as WorldOffset * SPACING;
+                let cells: Vec<_> = drawing
+                    .lines()
+                    .enumerate()
+                    .flat_map(|(row, line)| {
+                        line.bytes()
+                            .enumerate()
+                            .filter(|&(_, b)| b == b'O')
+                            .map(move |(col, _)| (x0 + col as WorldOffset, -(row as WorldOffset)))
+                    })
+                    .collect();
+
+                (code, period, dx, dy, x0, cells)
+            })
+            .collect();
+
+        let cells: Vec<_> = ships
+            .iter()
+            .flat_map(|ship| ship.5.iter().map(|&(x, y)| (x, y, 1)))
+            .collect();
+        let mut world = world_with(rule, &cells);
+
+        let area = |x0| {
+            Rect::new(
+                x0 - SPACING / 2,
+                SPACING / 2,
+                SPACING as u64,
+                SPACING as u64,
+            )
+        };
+        let longest = SHIPS.iter().map(|ship| ship.1).max().unwrap_or_default();
+
+        // Every generation of every ship, up to a period later
+        let mut generations = Vec::new();
+        for _ in 0..=longest {
+            let background = world.background();
+            generations.push(
+                ships
+                    .iter()
+                    .map(|ship| (background, world.live_cells_in(area(ship.4)).collect()))
+                    .collect::<Vec<(bool, Vec<_>)>>(),
+            );
+
+            world.step(1);
+        }
+
+        let mut phases = HashMap::new();
+        let mut max_len = 0;
+
+        for (i, &(code, period, dx, dy, _, ref cells)) in ships.iter().enumerate() {
+            let (background, last) = &generations[period as usize][i];
+
+            let mut last = last.clone();
+            last.sort();
+
+            let mut moved: Vec<_> = cells.iter().map(|&(x, y)| (x + dx, y + dy)).collect();
+            moved.sort();
+
+            if last != moved || *background {
+                continue;
+            }
+
+            for t in 0..8 {
+                let (dx, dy) = orient((dx, dy), t);
+
+                for found in &generations[..period as usize] {
+                    let cells: Vec<_> = found[i].1.iter().map(|&cell| orient(cell, t)).collect();
+
+                    max_len = max_len.max(cells.len());
+                    let ship = EscapingShip {
+                        code,
+                        cells: Vec::new(),
+                        dx,
+                        dy,
+                        period,
+                    };
+
+                    phases.insert(normalise(&cells), ship);
+                }
+            }
+        }
+
+        Ships { phases, max_len }
+    }
+}
+
+/// Apply the `t`-th of the 8 symmetries of the square to a cell or an offset: bit 0 swaps the
+/// axes, and bits 1 and 2 reverse the `x` and `y` axes
+fn orient((x, y): (WorldOffset, WorldOffset), t: u8) -> (WorldOffset, WorldOffset) {
+    let (x, y) = if t & 1 != 0 { (y, x) } else { (x, y) };
+    let x = if t & 2 != 0 { -x } else { x };
+    let y = if t & 4 != 0 { -y } else { y };
+
+    (x, y)
+}
+
+/// Cells relative to the south west corner of their bounding box, sorted
+fn normalise(cells: &[(WorldOffset, WorldOffset)]) -> Vec<(WorldOffset, WorldOffset)> {
+    let x0 = cells.iter().map(|c| c.0).min().unwrap_or_default();
+    let y0 = cells.iter().map(|c| c.1).min().unwrap_or_default();
+
+    let mut cells: Vec<_> = cells.iter().map(|&(x, y)| (x - x0, y - y0)).collect();
+    cells.sort();
+
+    cells
+}
+
+/// Find the known spaceships flying away from every other one of the live `cells` of a world,
+/// see [`remove_escaping`]
+pub(crate) fn escaping_ships(
+    cells: &[(WorldOffset, WorldOffset)],
+    ships: &Ships,
+) -> Vec<EscapingShip> {
+    if ships.phases.is_empty() {
+        return Vec::new();
+    }
+
+    // Sorted by column, so that the neighbours of a cell are found by binary search
+    let mut sorted = cells.to_vec();
+    sorted.sort_unstable();
+
+    let mut visited = vec![false; sorted.len()];
+
+    // Ships, along with their bounding box
+    let mut candidates = Vec::new();
+
+    for start in 0..sorted.len() {
+        if visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+
+        let mut object = vec![sorted[start]];
+        let mut i = 0;
+        while let Some(&(x, y)) = object.get(i) {
+            for x in x - REACH..=x + REACH {
+                let first = sorted.partition_point(|&cell| cell < (x, y - REACH));
+
+                for j in first..sorted.len() {
+                    if sorted[j] > (x, y + REACH) {
+                        break;
+                    }
+
+                    if !visited[j] {
+                        visited[j] = true;
+                        object.push(sorted[j]);
+                    }
+                }
+            }
+
+            i += 1;
+        }
+
+        if object.len() > ships.max_len {
+            continue;
+        }
+
+        if let Some(ship) = ships.phases.get(&normalise(&object)) {
+            let xs = object.iter().map(|c| c.0);
+            let ys = object.iter().map(|c| c.1);
+            let bbox = (
+                xs.clone().min().unwrap_or_default(),
+                ys.clone().min().unwrap_or_default(),
+                xs.max().unwrap_or_default(),
+                ys.max().unwrap_or_default(),
+            );
+
+            object.sort();
+            candidates.push((
+                EscapingShip {
+                    cells: object,
+                    ..ship.clone()
+                },
+                bbox,
+            ));
+        }
+    }
+
+    // Whether a cell lies ahead of a ship, in the quarter or the half of the plane it heads to
+    let ahead = |ship: &EscapingShip, (x0, y0, x1, y1), (x, y)| {
+        let ahead_x =
+            ship.dx == 0 || (ship.dx > 0 && x >= x0 - REACH) || (ship.dx < 0 && x <= x1 + REACH);
+        let ahead_y =
+            ship.dy == 0 || (ship.dy > 0 && y >= y0 - REACH) || (ship.dy < 0 && y <= y1 + REACH);
+
+        ahead_x && ahead_y
+    };
+
+    // The other cells ahead of each ship, found once since the ships ahead of it are few
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .map(|(ship, bbox)| {
+            let blockers: Vec<_> = sorted
+                .iter()
+                .copied()
+                .filter(|&cell| {
+                    ahead(&ship, bbox, cell) && ship.cells.binary_search(&cell).is_err()
+                })
+                .collect();
+
+            (ship, blockers)
+        })
+        .collect();
+
+    let mut escaping = Vec::new();
+    let mut escaped = HashSet::new();
+
+    // The ship in front of another one may escape first
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for (ship, blockers) in &mut candidates {
+            if ship.cells.is_empty() {
+                continue;
+            }
+
+            if blockers.iter().all(|cell| escaped.contains(cell)) {
+                escaped.extend(ship.cells.iter().copied());
+                escaping.push(EscapingShip {
+                    cells: std::mem::take(&mut ship.cells),
+                    ..*ship
+                });
+                changed = true;
+            }
+        }
+    }
+
+    escaping
+}
+
+/// Remove the known spaceships flying away from every other live cell of `world`, like
+/// apgsearch does before taking a census, and return them.
+///
+/// The glider, LWSS, MWSS and HWSS of Life are known, if they fly in the rule of `world`. A
+/// ship only escapes if no other live cell lies ahead of it: within the quarter of the plane it
+/// heads to for a glider, or the half of it for an orthogonal ship.
+pub fn remove_escaping(world: &mut World) -> Vec<EscapingShip> {
+    let ships = Ships::new(world.rule());
+    let cells: Vec<_> = world.live_cells().collect();
+    let escaping = escaping_ships(&cells, &ships);
+
+    for ship in &escaping {
+        for &(x, y) in &ship.cells {
+            world.set_state(x, y, 0);
+        }
+    }
+
+    escaping
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::census::picture;
+    use crate::census::world_with;
+    use crate::rule_set::B3S23;
+
+    use super::remove_escaping;
+
+    #[test]
+    fn test_remove_escaping() {
+        let lwss = [".O..O", "O....", "O...O", "OOOO."];
+
+        let cells = [
+            picture(0, 1, &["OO", "OO"]),
+            // Headed west and north east, away from the block
+            picture(-25, 10, &lwss),
+            picture(15, 20, &["OOO", "..O", ".O."]),
+            // Headed south east and west, towards the block
+            picture(-15, 15, &[".O.", "..O", "OOO"]),
+            picture(20, -10, &lwss),
+        ]
+        .concat();
+
+        let mut world = world_with(&B3S23, &cells);
+
+        let mut removed: Vec<_> = remove_escaping(&mut world)
+            .into_iter()
+            .map(|ship| (ship.code, ship.dx, ship.dy, ship.cells.len()))
+            .collect();
+        removed.sort();
+
+        assert_eq!(removed, [("xq4_153", 1, 1, 5), ("xq4_6frc", -2, 0, 9)]);
+        assert_eq!(world.live_cells().count(), 4 + 5 + 9);
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use crate::raster::Raster;
+use crate::world::Rect;
+use crate::world::World;
+
+/// Time between frames, in hundredths of a second
+pub const DEFAULT_DELAY: u16 = 10;
+
+/// Every frame is encoded using 8 bit color indices, one per density level of the [`Raster`]
+const MIN_CODE_SIZE: u8 = 8;
+
+/// Codes are at most 12 bits long, so the LZW code table can't grow any larger than this
+const MAX_CODES: u16 = 1 << 12;
+
+/// Render `steps` generations of `world`, `step_size` generations apart, to an animated GIF at
+/// `path`.
+///
+/// The first frame is the world as it is now, followed by a frame after each step, each shown
+/// for `delay` hundredths of a second like with [`GifEncoder::with_delay`]. Like
+/// [`export_png`], frames show the region `rect` of the world, with each pixel covering `scale` x
+/// `scale` cells.
+///
+/// [`export_png`]: crate::png::export_png
+pub fn export_gif<P: AsRef<Path>>(
+    world: &mut World,
+    rect: Rect,
+    steps: usize,
+    step_size: u64,
+    scale: u64,
+    delay: u16,
+    path: P,
+) -> io::Result<()> {
+    assert!(scale > 0, "scale must be at least 1 cell per pixel");
+
+    let (width, height) = (rect.w.div_ceil(scale), rect.h.div_ceil(scale));
+
+    assert!(
+        width <= u16::MAX as u64 && height <= u16::MAX as u64,
+        "GIF images are at most {0}x{0} pixels, got {width}x{height}",
+        u16::MAX
+    );
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut gif = GifEncoder::new(file, width as u16, height as u16)?.with_delay(delay);
+
+    gif.write_frame(&Raster::new(world, rect, scale))?;
+
+    for _ in 0..steps {
+        world.step(step_size);
+
+        gif.write_frame(&Raster::new(world, rect, scale))?;
+    }
+
+    gif.finish()
+}
+
+/// Writes [`Raster`]s as the frames of an endlessly looping GIF.
+///
+/// Like PNGs, live cells are drawn black on a white background, in shades of grey when zoomed out.
+///
+/// See: https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+pub struct GifEncoder<W: Write> {
+    w: W,
+
+    width: u16,
+    height: u16,
+
+    /// Time between frames, in hundredths of a second
+    delay: u16,
+}
+
+impl<W: Write> GifEncoder<W> {
+    /// Create a new encoder for frames of `width` by `height` pixels, and write the GIF header
+    pub fn new(mut w: W, width: u16, height: u16) -> io::Result<Self> {
+        w.write_all(b"GIF89a")?;
+
+        // Logical screen descriptor
+        w.write_all(&width.to_le_bytes())?;
+        w.write_all(&height.to_le_bytes())?;
+        w.write_all(&[
+            // Global color table of 2^(7 + 1) colors, with 8 bits per primary color
+            0b1111_0111,
+            // Background color index
+            0,
+            // Pixel aspect ratio
+            0,
+        ])?;
+
+        // Global color table. The color index is the density of live cells, so it goes from
+        // white to black.
+        for i in 0..=255u8 {
+            let grey = 255 - i;
+
+            w.write_all(&[grey, grey, grey])?;
+        }
+
+        // Netscape application extension, to loop forever
+        w.write_all(&[0x21, 0xFF, 0x0B])?;
+        w.write_all(b"NETSCAPE2.0")?;
+        w.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+
+        Ok(Self {
+            w,
+            width,
+            height,
+            delay: DEFAULT_DELAY,
+        })
+    }
+
+    /// Set the time between frames, in hundredths of a second
+    pub fn with_delay(mut self, delay: u16) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn write_frame(&mut self, raster: &Raster) -> io::Result<()> {
+        assert_eq!(
+            (raster.width(), raster.height()),
+            (self.width as usize, self.height as usize),
+            "Every frame should be the same size"
+        );
+
+        // Graphic control extension
+        self.w.write_all(&[0x21, 0xF9, 0x04])?;
+        // Leave the frame in place for the next one to be drawn over, no transparency
+        self.w.write_all(&[0b0000_0100])?;
+        self.w.write_all(&self.delay.to_le_bytes())?;
+        self.w.write_all(&[0x00, 0x00])?;
+
+        // Image descriptor, covering the whole screen
+        self.w.write_all(&[0x2C])?;
+        self.w.write_all(&0u16.to_le_bytes())?;
+        self.w.write_all(&0u16.to_le_bytes())?;
+        self.w.write_all(&self.width.to_le_bytes())?;
+        self.w.write_all(&self.height.to_le_bytes())?;
+        self.w.write_all(&[0])?;
+
+        self.w.write_all(&[MIN_CODE_SIZE])?;
+
+        // Image data is split into sub-blocks of at most 255 bytes
+        for block in lzw(raster.pixels()).chunks(255) {
+            self.w.write_all(&[block.len() as u8])?;
+            self.w.write_all(block)?;
+        }
+        self.w.write_all(&[0])?;
+
+        Ok(())
+    }
+
+    /// Write the GIF trailer
+    pub fn finish(mut self) -> io::Result<()> {
+        self.w.write_all(&[0x3B])?;
+        self.w.flush()
+    }
+}
+
+/// Compress `indices` using the variable length LZW flavor of GIFs
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1 << MIN_CODE_SIZE;
+    let end = clear + 1;
+
+    let mut w = CodeWriter::default();
+
+    // Maps a known string, given as its prefix code followed by a byte, to its code
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+
+    w.write_code(clear, size);
+
+    let Some((&first, indices)) = indices.split_first() else {
+        w.write_code(end, size);
+
+        return w.finish();
+    };
+
+    let mut prefix = first as u16;
+
+    for &b in indices {
+        if let Some(&code) = table.get(&(prefix, b)) {
+            prefix = code;
+
+            continue;
+        }
+
+        w.write_code(prefix, size);
+
+        if next == MAX_CODES {
+            // The table is full, start over
+            w.write_code(clear, size);
+
+            table.clear();
+            next = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        } else {
+            if next >= 1 << size {
+                size += 1;
+            }
+
+            table.insert((prefix, b), next);
+            next += 1;
+        }
+
+        prefix = b as u16;
+    }
+
+    w.write_code(prefix, size);
+    w.write_code(end, size);
+
+    w.finish()
+}
+
+#[derive(Default)]
+struct CodeWriter {
+    out: Vec<u8>,
+    acc: u32,
+    n: u8,
+}
+
+impl CodeWriter {
+    /// Write a `size` bit code, least significant bit first
+    fn write_code(&mut self, code: u16, size: u8) {
+        self.acc |= (code as u32) << self.n;
+        self.n += size;
+
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.n > 0 {
+            self.out.push(self.acc as u8);
+        }
+
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /// A straightforward GIF LZW decoder, to check our encoder against
+    fn unlzw(bytes: &[u8]) -> Vec<u8> {
+        let (clear, end) = (256, 257);
+
+        let mut bits = bytes
+            .iter()
+            .flat_map(|b| (0..8).map(move |i| (b >> i) & 1 == 1));
+        let mut read = |size: u8| -> u16 {
+            (0..size).fold(0, |code, i| code | (bits.next().unwrap() as u16) << i)
+        };
+
+        let mut out = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut size = 9;
+        let mut prev: Option<Vec<u8>> = None;
+
+        loop {
+            let code = read(size);
+
+            if code == clear {
+                table = (0..=255).map(|b| vec![b]).chain([vec![], vec![]]).collect();
+                size = 9;
+                prev = None;
+
+                continue;
+            }
+
+            if code == end {
+                return out;
+            }
+
+            let entry = match (table.get(code as usize), &prev) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(prev)) => [prev.as_slice(), &prev[..1]].concat(),
+                (None, None) => panic!("Unknown code {code}"),
+            };
+
+            if let Some(prev) = prev
+                && table.len() < 4096
+            {
+                table.push([prev.as_slice(), &entry[..1]].concat());
+
+                if table.len() == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+
+            out.extend_from_slice(&entry);
+            prev = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        // Long enough to fill the code table a few times over
+        let mut seed = 1u32;
+        let indices: Vec<u8> = (0..50_000)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+
+                if i % 7 == 0 { (seed >> 16) as u8 } else { 0 }
+            })
+            .collect();
+
+        assert_eq!(unlzw(&super::lzw(&indices)), indices);
+        assert_eq!(unlzw(&super::lzw(&[])), []);
+    }
+}
@@ -1,9 +1,20 @@
 pub mod camera;
 pub mod cell;
+pub mod census;
+pub mod escape;
+pub mod gif;
 pub mod parse_rle;
+pub mod png;
+pub mod raster;
 pub mod rule_set;
+pub mod rule_table;
+pub mod snapshot;
+pub mod soup;
+pub mod svg;
+pub mod transform;
 pub mod world;
 
+mod checksum;
 mod parse_util;
 
 pub type ScreenSize = u16;
@@ -219,11 +219,6 @@ pub fn take_until(b: u8, bytes: &[u8]) -> (Option<&[u8]>, &[u8]) {
     take_until_fn(|a| a == b, bytes)
 }
 
-/// Like `take_util`, but stops at the first ascii whitespace character found, without consuming it.
-pub fn take_until_ws(bytes: &[u8]) -> (Option<&[u8]>, &[u8]) {
-    take_until_fn(|a| a.is_ascii_whitespace(), bytes)
-}
-
 /// Like `take_until_fn`, but also consumes the first byte satisfying `P`.
 #[inline]
 pub fn take_with_fn<P>(p: P, bytes: &[u8]) -> (Option<&[u8]>, &[u8])
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use crate::checksum::adler32;
+use crate::checksum::crc32_update;
+use crate::raster::Raster;
+use crate::world::Rect;
+use crate::world::World;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Greyscale, see: https://www.w3.org/TR/png/#6Colour-values
+const COLOR_TYPE_GREYSCALE: u8 = 0;
+
+/// Render the region `rect` of `world` to a PNG file at `path`.
+///
+/// Each pixel covers `scale` x `scale` cells. Live cells are drawn black on a white background,
+/// and when more than one cell is covered by a pixel, it is drawn in a shade of grey according to
+/// the density of live cells under it.
+pub fn export_png<P: AsRef<Path>>(
+    world: &World,
+    rect: Rect,
+    scale: u64,
+    path: P,
+) -> io::Result<()> {
+    let raster = Raster::new(world, rect, scale);
+
+    let mut file = BufWriter::new(File::create(path)?);
+    write_png(&raster, &mut file)?;
+
+    file.flush()
+}
+
+/// Encode `raster` as an 8 bit greyscale PNG
+pub fn write_png<W: Write>(raster: &Raster, mut w: W) -> io::Result<()> {
+    let (width, height) = (raster.width(), raster.height());
+
+    assert!(width > 0 && height > 0, "PNG images cannot be empty");
+
+    w.write_all(&PNG_SIGNATURE)?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.push(8); // Bit depth
+    ihdr.push(COLOR_TYPE_GREYSCALE);
+    ihdr.push(0); // Compression method: deflate
+    ihdr.push(0); // Filter method: adaptive
+    ihdr.push(0); // Interlace method: none
+    write_chunk(&mut w, b"IHDR", &ihdr)?;
+
+    // Each scanline is preceded by its filter type. We never filter.
+    let mut data = Vec::with_capacity((width + 1) * height);
+    for row in raster.pixels().chunks(width) {
+        data.push(0);
+        data.extend(row.iter().map(|density| 255 - density));
+    }
+
+    write_chunk(&mut w, b"IDAT", &zlib(&data))?;
+    write_chunk(&mut w, b"IEND", &[])?;
+
+    Ok(())
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+
+    let crc = crc32_update(crc32_update(!0, kind), data);
+    w.write_all(&(!crc).to_be_bytes())
+}
+
+/// Wrap `bytes` in a zlib stream.
+///
+/// See: https://www.rfc-editor.org/rfc/rfc1950
+fn zlib(bytes: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, no preset dictionary and the fastest compression level.
+    let mut out = vec![0x78, 0x01];
+
+    out.extend(deflate(bytes));
+    out.extend(adler32(bytes).to_be_bytes());
+
+    out
+}
+
+/// Base lengths of the deflate length codes `257..=285`, with their number of extra bits
+#[rustfmt::skip]
+const LENGTH_BASES: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0),
+    (11, 1), (13, 1), (15, 1), (17, 1),
+    (19, 2), (23, 2), (27, 2), (31, 2),
+    (35, 3), (43, 3), (51, 3), (59, 3),
+    (67, 4), (83, 4), (99, 4), (115, 4),
+    (131, 5), (163, 5), (195, 5), (227, 5),
+    (258, 0),
+];
+
+/// Compress `bytes` into a single deflate block using the fixed Huffman codes.
+///
+/// The only back references we look for are runs of a repeated byte (i.e. at distance 1). Our
+/// images are mostly made up of long stretches of empty space, so this is where nearly all of the
+/// savings are.
+///
+/// See: https://www.rfc-editor.org/rfc/rfc1951
+fn deflate(bytes: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter::default();
+
+    // Final block, compressed with fixed Huffman codes
+    w.write_bits(1, 1);
+    w.write_bits(0b01, 2);
+
+    let mut i = 0;
+    while i < bytes.len() {
+        w.write_literal(bytes[i] as u16);
+
+        let b = bytes[i];
+        i += 1;
+
+        // Count how many times the byte we just wrote repeats
+        let mut run = 0;
+        while i + run < bytes.len() && bytes[i + run] == b && run < 258 {
+            run += 1;
+        }
+
+        if run >= 3 {
+            w.write_length(run as u16);
+
+            // Distance code 0 (distance 1), with no extra bits
+            w.write_huffman(0, 5);
+
+            i += run;
+        }
+    }
+
+    // End of block
+    w.write_literal(256);
+
+    w.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    n: u8,
+}
+
+impl BitWriter {
+    /// Write the `n` low bits of `bits`, least significant bit first
+    fn write_bits(&mut self, bits: u32, n: u8) {
+        self.acc |= bits << self.n;
+        self.n += n;
+
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit
+    fn write_huffman(&mut self, code: u32, n: u8) {
+        let reversed = code.reverse_bits() >> (32 - n);
+
+        self.write_bits(reversed, n);
+    }
+
+    /// Write a literal/length symbol using the fixed Huffman code
+    fn write_literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+
+        match symbol {
+            0..=143 => self.write_huffman(0b0011_0000 + symbol, 8),
+            144..=255 => self.write_huffman(0b1_1001_0000 + symbol - 144, 9),
+            256..=279 => self.write_huffman(symbol - 256, 7),
+            _ => self.write_huffman(0b1100_0000 + symbol - 280, 8),
+        }
+    }
+
+    /// Write the length of a back reference, between 3 and 258
+    fn write_length(&mut self, len: u16) {
+        let i = LENGTH_BASES
+            .iter()
+            .rposition(|&(base, _)| base <= len)
+            .expect("Lengths start at 3");
+        let (base, extra) = LENGTH_BASES[i];
+
+        self.write_literal(257 + i as u16);
+        self.write_bits((len - base) as u32, extra);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.n > 0 {
+            self.out.push(self.acc as u8);
+        }
+
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rule_set::B3S23;
+    use crate::world::Rect;
+    use crate::world::World;
+
+    use super::Raster;
+
+    #[test]
+    fn test_write_png() -> std::io::Result<()> {
+        let mut world = World::new(B3S23);
+        world.set(0, 0);
+
+        let raster = Raster::new(&world, Rect::new(-2, 1, 4, 3), 1);
+
+        let mut png = Vec::new();
+        super::write_png(&raster, &mut png)?;
+
+        assert_eq!(png[..8], super::PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 4, 0, 0, 0, 3]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use crate::WorldOffset;
+use crate::cell::CellBuf;
+use crate::world::Rect;
+use crate::world::World;
+
+/// A greyscale image of a region of a [`World`].
+///
+/// Each pixel covers a square of `scale` x `scale` cells and holds the density of live cells in
+/// that square, from `0` (all dead) to `255` (all alive). At a scale of 1, every pixel is exactly
+/// one cell.
+pub struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Raster {
+    /// Render the region `rect` of `world`, where each pixel covers `scale` x `scale` cells.
+    pub fn new(world: &World, rect: Rect, scale: u64) -> Self {
+        assert!(scale > 0, "scale must be at least 1 cell per pixel");
+
+        let width = rect.w.div_ceil(scale) as usize;
+        let height = rect.h.div_ceil(scale) as usize;
+
+        let mut renderer = Renderer {
+            buf: &world.buf,
+            rect,
+            scale: scale as i128,
+            width,
+            counts: vec![0; width * height],
+            populations: HashMap::new(),
+        };
+
+        // Offset of the world's top left cell from the top left of `rect`
+        let half = 1 << (world.depth - 1);
+        let col = -half - rect.x;
+        let row = rect.y - (half - 1);
+
+        renderer.draw(world.root, world.depth, col, row);
+
+        let (w, h) = (rect.w as u128, rect.h as u128);
+        let scale = scale as u128;
+
+        let pixels = renderer
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| {
+                let (px, py) = ((i % width) as u128, (i / width) as u128);
+
+                // Pixels on the right and bottom edges may not be entirely inside `rect`
+                let area = scale.min(w - px * scale) * scale.min(h - py * scale);
+
+                ((count * 255 + area / 2) / area) as u8
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixels of the raster in row-major order, starting from the top left
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// The density of the pixel at (`x`, `y`), where (0, 0) is the top left pixel
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+struct Renderer<'a> {
+    buf: &'a CellBuf,
+
+    rect: Rect,
+
+    /// Sidelength of a pixel, in cells
+    scale: i128,
+
+    /// Width of the raster, in pixels
+    width: usize,
+
+    /// Number of live cells in each pixel
+    counts: Vec<u128>,
+
+    /// Memoized populations of the cells we have drawn, by index
+    populations: HashMap<usize, u128>,
+}
+
+impl Renderer<'_> {
+    /// Draw the `2^depth` cell at `ptr`, whose top left corner is `col` cells east and `row`
+    /// cells south of the top left of `rect`.
+    fn draw(&mut self, ptr: usize, depth: u8, col: WorldOffset, row: WorldOffset) {
+        let cell = self.buf[ptr];
+
+        // Nothing to draw. This is where the quadtree saves us from visiting empty space.
+        if cell.is_void() {
+            return;
+        }
+
+        let size = 1 << depth;
+        let (w, h) = (self.rect.w as WorldOffset, self.rect.h as WorldOffset);
+
+        // Entirely outside the region
+        if col >= w || row >= h || col + size <= 0 || row + size <= 0 {
+            return;
+        }
+
+        let inside = col >= 0 && row >= 0 && col + size <= w && row + size <= h;
+        let one_pixel = col / self.scale == (col + size - 1) / self.scale
+            && row / self.scale == (row + size - 1) / self.scale;
+
+        // The whole cell falls within a single pixel, there's no need to look any closer
+        if inside && one_pixel {
+            let population = self.population(ptr);
+            self.add(col, row, population);
+
+            return;
+        }
+
+        if cell.is_leaf() {
+            for (dx, dy) in cell.leaf_cells(self.buf) {
+                let (col, row) = (col + dx as WorldOffset, row + dy as WorldOffset);
+
+                if 0 <= col && col < w && 0 <= row && row < h {
+                    self.add(col, row, 1);
+                }
+            }
+        } else {
+            let half = size >> 1;
+
+            self.draw(cell.nw, depth - 1, col, row);
+            self.draw(cell.ne, depth - 1, col + half, row);
+            self.draw(cell.sw, depth - 1, col, row + half);
+            self.draw(cell.se, depth - 1, col + half, row + half);
+        }
+    }
+
+    /// Add `n` live cells to the pixel containing the cell at (`col`, `row`)
+    fn add(&mut self, col: WorldOffset, row: WorldOffset, n: u128) {
+        let (px, py) = ((col / self.scale) as usize, (row / self.scale) as usize);
+
+        self.counts[py * self.width + px] += n;
+    }
+
+    /// Count the live cells in the cell at `ptr`
+    fn population(&mut self, ptr: usize) -> u128 {
+        let cell = self.buf[ptr];
+
+        if cell.is_void() {
+            return 0;
+        }
+
+        if let Some(population) = self.populations.get(&ptr) {
+            return *population;
+        }
+
+        let population = match self.buf.rules(cell) {
+            Some(rules) => rules.iter().map(|r| r.count_ones() as u128).sum(),
+            None => {
+                self.population(cell.nw)
+                    + self.population(cell.ne)
+                    + self.population(cell.sw)
+                    + self.population(cell.se)
+            }
+        };
+
+        self.populations.insert(ptr, population);
+
+        population
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::Rect;
+    use crate::world::glider;
+
+    use super::Raster;
+
+    #[test]
+    fn test_raster_one_cell_per_pixel() {
+        let world = glider();
+        let raster = Raster::new(&world, Rect::new(0, 2, 3, 3), 1);
+
+        let rows: Vec<_> = raster.pixels().chunks(3).collect();
+
+        assert_eq!(rows, [[0, 255, 0], [0, 0, 255], [255, 255, 255]]);
+    }
+
+    #[test]
+    fn test_raster_density() {
+        let world = glider();
+        let raster = Raster::new(&world, Rect::new(0, 3, 4, 4), 2);
+
+        // The top left pixel has 1 live cell out of 4, the bottom pixels have 2
+        assert_eq!((raster.width(), raster.height()), (2, 2));
+        assert_eq!(raster.pixels(), [64, 0, 128, 128]);
+    }
+}
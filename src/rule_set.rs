@@ -1,9 +1,82 @@
+use std::sync::Arc;
+
 use thiserror::Error;
 
 use crate::parse_util;
+use crate::rule_table::RuleTree;
+
+/// Offsets of the 8 neighbours of a cell in a 4x4 rule, from the bit of the cell itself. Goes: NW,
+/// N, NE, W, E, SW, S, SE.
+const NEIGHBOURS: [i8; 8] = [5, 4, 3, 1, -1, -3, -4, -5];
+
+/// Bit of the bottom right cell of the center 2x2 cell in a 4x4 rule
+const CELL_BIT: i8 = 5;
+
+/// Hensel letters, in the order their configurations are numbered in [`Hensel`]
+pub const HENSEL_LETTERS: &[u8; 13] = b"cekainyqjrtwz";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Number of base64 digits in a `MAP` rulestring, 6 bits each
+const MAP_LEN: usize = 512usize.div_ceil(6);
+
+/// Cell states are stored in a byte, see [`CellBuf`](crate::cell::CellBuf)
+pub const MAX_STATES: u16 = 256;
+
+/// Number of configurations of `n` neighbours, up to rotations and reflections
+const HENSEL_CONFIGS: [u8; 9] = [1, 2, 6, 10, 13, 10, 6, 2, 1];
 
-const NBHD_MASK: u16 = 0b0000_0111_0101_0111;
-const CELL_MASK: u16 = 0b0000_0000_0010_0000;
+/// One configuration for each Hensel letter of up to 4 neighbours. Configurations of `n > 4`
+/// neighbours are the complement of the configuration of `8 - n` neighbours with the same letter.
+///
+/// Neighbours are given as 8 bits: NW, N, NE, W, E, SW, S, SE, starting from the most significant
+/// bit. Bits are grouped by row.
+///
+/// See: https://conwaylife.com/wiki/Isotropic_non-totalistic_rule
+#[rustfmt::skip]
+#[allow(clippy::unusual_byte_groupings)]
+const HENSEL_NEIGHBOURHOODS: [&[u8]; 5] = [
+    &[0],
+    &[
+        0b100_00_000, // c
+        0b010_00_000, // e
+    ],
+    &[
+        0b101_00_000, // c
+        0b010_01_000, // e
+        0b010_00_001, // k
+        0b110_00_000, // a
+        0b010_00_010, // i
+        0b100_00_001, // n
+    ],
+    &[
+        0b101_00_001, // c
+        0b010_11_000, // e
+        0b010_10_001, // k
+        0b110_10_000, // a
+        0b100_10_100, // i
+        0b101_10_000, // n
+        0b101_00_010, // y
+        0b100_10_001, // q
+        0b001_01_010, // j
+        0b011_00_010, // r
+    ],
+    &[
+        0b101_00_101, // c
+        0b010_11_010, // e
+        0b011_10_001, // k
+        0b111_10_000, // a
+        0b101_11_000, // i
+        0b111_00_001, // n
+        0b101_00_011, // y
+        0b110_10_001, // q
+        0b010_11_100, // j
+        0b011_01_010, // r
+        0b111_00_010, // t
+        0b100_10_011, // w
+        0b110_00_011, // z
+    ],
+];
 
 // Count the bits using Brian Kernighan's way
 // See: http://graphics.stanford.edu/~seander/bithacks.html#CountBitsSetKernighan
@@ -21,14 +94,47 @@ fn count_bits(mut x: u16) -> u8 {
 /// Rules of Conway's Game of Life.
 pub const B3S23: RuleSet = RuleSet::new(0b1000, 0b1100);
 
+/// Common names of rules, along with their rulestring. Each rule has a single name, so that
+/// [`RuleSet::canonical_name`] can give it back.
+///
+/// See: https://conwaylife.com/wiki/List_of_Life-like_rules
+pub const RULE_NAMES: &[(&str, &str)] = &[
+    ("Life", "B3/S23"),
+    ("HighLife", "B36/S23"),
+    ("DayAndNight", "B3678/S34678"),
+    ("Seeds", "B2/S"),
+    ("LifeWithoutDeath", "B3/S012345678"),
+    ("2x2", "B36/S125"),
+    ("Maze", "B3/S12345"),
+    ("Mazectric", "B3/S1234"),
+    ("Replicator", "B1357/S1357"),
+    ("Diamoeba", "B35678/S5678"),
+    ("Morley", "B368/S245"),
+    ("Anneal", "B4678/S35678"),
+    ("LongLife", "B345/S5"),
+    ("Amoeba", "B357/S1358"),
+    ("Coral", "B3/S45678"),
+    ("DryLife", "B37/S23"),
+    ("Gnarl", "B1/S1"),
+    ("Serviettes", "B234/S"),
+    ("Assimilation", "B345/S4567"),
+    ("Coagulations", "B378/S235678"),
+    ("WalledCities", "B45678/S2345"),
+    ("BriansBrain", "B2/S/C3"),
+    ("StarWars", "B2/S345/C4"),
+];
+
 /// # Representation
-/// Life rules are represented as
+/// Outer totalistic life rules are represented as
 /// ```notrust
 /// |------birth------|
 /// 0000_0000_0000_0000_0000_0000_0000_0000
 ///                     |----survival-----|
 /// ```
 ///
+/// Isotropic non-totalistic rules are represented with a [`Hensel`] instead, and any other rule
+/// with the fate of a cell for each of its neighbourhoods, like `MAP` rulestrings.
+///
 /// # Examples
 /// ```notrust
 /// b3s23:                0000_0000_0000_1000_0000_0000_0000_1100
@@ -42,9 +148,150 @@ pub const B3S23: RuleSet = RuleSet::new(0b1000, 0b1100);
 pub struct RuleSet {
     rule: u32,
 
+    /// Set for isotropic non-totalistic rules, in which case `rule` only holds the numbers of
+    /// neighbours for which every configuration causes a birth or survival
+    hensel: Option<Hensel>,
+
+    /// Set for rules which are neither, given as their [`RuleSet::transitions`]. `rule` then only
+    /// holds the numbers of neighbours for which every neighbourhood causes a birth or survival
+    map: Option<Box<[bool; 512]>>,
+
+    /// Cells counted by `rule`
+    nbhd: Neighbourhood,
+
+    /// Number of states a cell can be in. Generations rules have more than 2, where live cells
+    /// which don't survive decay through every state past 1 before they die.
+    states: u16,
+
+    /// Set for rules given by a rule tree, in which case the other fields are meaningless
+    tree: Option<Arc<RuleTree>>,
+
     ext: Option<RuleExtension>,
 }
 
+/// Cells which count as the neighbours of a cell
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /// The 8 surrounding cells
+    #[default]
+    Moore,
+
+    /// The 4 orthogonally adjacent cells
+    VonNeumann,
+
+    /// The 6 surrounding cells of a hexagonal grid. Like Golly, we emulate it on the square grid
+    /// by shearing it, so that every cell but the NE and SW ones counts.
+    Hexagonal,
+}
+
+impl Neighbourhood {
+    /// Cells which count, given like in [`HENSEL_NEIGHBOURHOODS`]
+    #[allow(clippy::unusual_byte_groupings)]
+    const fn mask(self) -> u8 {
+        match self {
+            Self::Moore => 0b111_11_111,
+            Self::VonNeumann => 0b010_11_010,
+            Self::Hexagonal => 0b110_11_011,
+        }
+    }
+
+    /// Number of neighbours of a cell
+    pub const fn size(self) -> u8 {
+        self.mask().count_ones() as u8
+    }
+}
+
+/// Births and survivals of an isotropic non-totalistic rule.
+///
+/// Bit `i` of `births[n]` is set if `n` live neighbours in the configuration of the `i`th letter of
+/// [`HENSEL_LETTERS`] cause a birth. There is a single configuration of 0 and 8 neighbours, bit 0.
+/// The same goes for `survivals`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hensel {
+    pub births: [u16; 9],
+    pub survivals: [u16; 9],
+}
+
+impl Hensel {
+    /// All the configurations of `n` neighbours
+    const fn all(n: usize) -> u16 {
+        (1 << HENSEL_CONFIGS[n]) - 1
+    }
+
+    /// Numbers of neighbours for which every configuration is set, packed like the births and
+    /// survivals of a [`RuleSet`]
+    fn totalistic(configs: &[u16; 9]) -> u16 {
+        (0..9)
+            .filter(|&n| configs[n] == Self::all(n))
+            .fold(0, |acc, n| acc | 1 << n)
+    }
+
+    fn is_totalistic(&self) -> bool {
+        (0..9).all(|n| {
+            [self.births[n], self.survivals[n]]
+                .iter()
+                .all(|&c| c == 0 || c == Self::all(n))
+        })
+    }
+
+    /// For each configuration of 8 neighbours, given like in [`HENSEL_NEIGHBOURHOODS`], its number
+    /// of live neighbours and the index of its letter
+    fn configurations() -> [(u8, u8); 256] {
+        let mut configs = [(0, 0); 256];
+
+        for (n, neighbourhoods) in HENSEL_NEIGHBOURHOODS.iter().enumerate() {
+            for (i, &nbhd) in neighbourhoods.iter().enumerate() {
+                for nbhd in symmetries(nbhd) {
+                    configs[nbhd as usize] = (n as u8, i as u8);
+
+                    // Configurations of 4 neighbours are their own complements
+                    if n < 4 {
+                        configs[!nbhd as usize] = (8 - n as u8, i as u8);
+                    }
+                }
+            }
+        }
+
+        configs
+    }
+}
+
+/// The 8 rotations and reflections of a configuration of 8 neighbours
+fn symmetries(nbhd: u8) -> [u8; 8] {
+    // Positions of the neighbours, in the same order as their bits
+    const POSITIONS: [(i8, i8); 8] = [
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+        (-1, 0),
+        (1, 0),
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+    ];
+
+    let bit = |(x, y)| 7 - POSITIONS.iter().position(|&p| p == (x, y)).unwrap();
+
+    let transform = |f: &dyn Fn((i8, i8)) -> (i8, i8)| {
+        POSITIONS
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| nbhd & (1 << (7 - i)) != 0)
+            .fold(0u8, |acc, (_, &p)| acc | 1 << bit(f(p)))
+    };
+
+    [
+        transform(&|(x, y)| (x, y)),
+        transform(&|(x, y)| (-y, x)),
+        transform(&|(x, y)| (-x, -y)),
+        transform(&|(x, y)| (y, -x)),
+        transform(&|(x, y)| (-x, y)),
+        transform(&|(x, y)| (x, -y)),
+        transform(&|(x, y)| (y, x)),
+        transform(&|(x, y)| (-y, -x)),
+    ]
+}
+
 impl Default for RuleSet {
     fn default() -> Self {
         B3S23
@@ -53,31 +300,110 @@ impl Default for RuleSet {
 
 impl std::fmt::Debug for RuleSet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut rule_str = String::from("b");
+        let rule_str = if self.map.is_some() || self.tree.is_some() {
+            self.rulestring()
+        } else {
+            self.rulestring().to_ascii_lowercase()
+        };
 
-        // Birth rules (upper 16 bits)
-        for i in 0..9 {
-            if (self.rule >> (16 + i)) & 1 != 0 {
-                rule_str.push((b'0' + i) as char);
-            }
+        f.debug_struct("RuleSet")
+            .field("rule", &rule_str)
+            .field("ext", &self.ext)
+            .finish()
+    }
+}
+
+/// Write the rule in its canonical form, like `B3/S23:T100,50`. Rules which are neither outer
+/// totalistic nor isotropic are written as `MAP` rulestrings, and rule trees by their name.
+impl std::fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.rulestring())?;
+
+        match &self.ext {
+            Some(ext) => write!(f, "{ext}"),
+            None => Ok(()),
         }
+    }
+}
 
-        rule_str.push_str("/s");
+/// Parse a rule in any of the notations RLE files use: `B3/S23`, `S23/B3`, `3/23`, `MAP` and
+/// named rules, optionally followed by an extension like `:T100,50`
+impl std::str::FromStr for RuleSet {
+    type Err = RuleError;
 
-        // Survival rules (lower 16 bits)
-        for i in 0..9 {
-            if (self.rule >> i) & 1 != 0 {
-                rule_str.push((b'0' + i) as char);
-            }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Rulestrings need something after them, see `parse_rule`
+        let s = format!("{} ", s.trim());
+
+        let (rule, rest) = match parse_rule(s.as_bytes()) {
+            Err(RuleError::NoBirths) => parse_nameless_rule(s.as_bytes())?,
+            res => res?,
+        };
+
+        let rest = rest.trim_ascii();
+        if !rest.is_empty() {
+            return Err(RuleError::TrailingCharacters {
+                got: String::from_utf8_lossy(rest).into_owned(),
+            });
         }
 
-        f.debug_struct("RuleSet")
-            .field("rule", &rule_str)
-            .field("ext", &self.ext)
-            .finish()
+        Ok(rule)
     }
 }
 
+/// The 8 neighbours of a neighbourhood given like in [`RuleSet::transitions`], given like in
+/// [`HENSEL_NEIGHBOURHOODS`]
+fn neighbours(i: usize) -> u8 {
+    ((i >> 5) << 4 | (i & 0xF)) as u8
+}
+
+/// Every configuration of the numbers of neighbours in `counts`
+fn totalistic_configs(counts: u16) -> [u16; 9] {
+    std::array::from_fn(|n| {
+        if counts & (1 << n) != 0 {
+            Hensel::all(n)
+        } else {
+            0
+        }
+    })
+}
+
+/// Write births or survivals in Hensel notation, where configurations are either listed or
+/// excluded, whichever is shorter
+fn fmt_configs(configs: &[u16; 9]) -> String {
+    let mut s = String::new();
+
+    for (n, &c) in configs.iter().enumerate() {
+        if c == 0 {
+            continue;
+        }
+
+        s.push((b'0' + n as u8) as char);
+
+        let all = Hensel::all(n);
+        if c == all {
+            continue;
+        }
+
+        let letters = |c: u16| {
+            HENSEL_LETTERS
+                .iter()
+                .enumerate()
+                .filter(move |&(i, _)| c & (1 << i) != 0)
+                .map(|(_, &l)| l as char)
+        };
+
+        if c.count_ones() <= (all & !c).count_ones() {
+            s.extend(letters(c));
+        } else {
+            s.push('-');
+            s.extend(letters(all & !c));
+        }
+    }
+
+    s
+}
+
 impl RuleSet {
     /// Create a new `RuleSet` for the given births and survivals. For both `b` and
     /// `s`, numbers are set on a bit basis. For instance if bit `i` in `b` is on, it
@@ -90,6 +416,11 @@ impl RuleSet {
 
         Self {
             rule: (b as u32) << 16 | s as u32,
+            hensel: None,
+            map: None,
+            nbhd: Neighbourhood::Moore,
+            states: 2,
+            tree: None,
             ext: None,
         }
     }
@@ -100,18 +431,285 @@ impl RuleSet {
 
         Self {
             rule: (b as u32) << 16 | s as u32,
+            hensel: None,
+            map: None,
+            nbhd: Neighbourhood::Moore,
+            states: 2,
+            tree: None,
             ext: Some(ext),
         }
     }
 
+    /// Create a new outer totalistic `RuleSet` which counts the neighbours of `nbhd`, like
+    /// [`RuleSet::new`] does for the Moore neighbourhood. Births and survivals past the size of
+    /// the neighbourhood are ignored.
+    pub const fn with_neighbourhood(b: u16, s: u16, nbhd: Neighbourhood) -> Self {
+        let mask = (1 << (nbhd.size() + 1)) - 1;
+        let b = b & mask;
+        let s = s & mask;
+
+        Self {
+            rule: (b as u32) << 16 | s as u32,
+            hensel: None,
+            map: None,
+            nbhd,
+            states: 2,
+            tree: None,
+            ext: None,
+        }
+    }
+
+    /// Create a new isotropic non-totalistic `RuleSet`. Rules that turn out to be outer
+    /// totalistic are stored as such.
+    pub fn isotropic(hensel: Hensel) -> Self {
+        let b = Hensel::totalistic(&hensel.births);
+        let s = Hensel::totalistic(&hensel.survivals);
+
+        Self {
+            hensel: (!hensel.is_totalistic()).then_some(hensel),
+            ..Self::new(b, s)
+        }
+    }
+
+    /// Create a new `RuleSet` out of the fate of a cell for each of its neighbourhoods, see
+    /// [`RuleSet::transitions`]. Rules that turn out to be isotropic or outer totalistic are stored
+    /// as such.
+    pub fn from_transitions(transitions: [bool; 512]) -> Self {
+        let configs = Hensel::configurations();
+
+        let mut hensel = Hensel {
+            births: [0; 9],
+            survivals: [0; 9],
+        };
+
+        for (i, _) in transitions.iter().enumerate().filter(|&(_, &t)| t) {
+            let (n, letter) = configs[neighbours(i) as usize];
+
+            let configs = if i & 0x10 != 0 {
+                &mut hensel.survivals
+            } else {
+                &mut hensel.births
+            };
+
+            configs[n as usize] |= 1 << letter;
+        }
+
+        let rule = Self::isotropic(hensel);
+
+        if rule.transitions() == transitions {
+            return rule;
+        }
+
+        // Numbers of neighbours for which every neighbourhood causes a birth or survival
+        let counts = |alive: usize| {
+            (0..9).fold(0, |acc, n| {
+                let all = (0..512)
+                    .filter(|&i| i & 0x10 == alive && count_bits(neighbours(i) as u16) == n)
+                    .all(|i| transitions[i]);
+
+                if all { acc | 1 << n } else { acc }
+            })
+        };
+
+        Self {
+            map: Some(Box::new(transitions)),
+            ..Self::new(counts(0), counts(0x10))
+        }
+    }
+
+    /// Parse a `MAP` rulestring, which gives the fate of a cell for each of its 512 neighbourhoods
+    /// in base64. See [`RuleSet::transitions`] for the order of the neighbourhoods.
+    ///
+    /// See: https://conwaylife.com/wiki/Non-isotropic_rule
+    pub fn from_map(map: &str) -> Result<Self, RuleError> {
+        let Some(map) = map.strip_prefix("MAP") else {
+            return Err(RuleError::NoMap);
+        };
+
+        // Padding is optional
+        let map = map.strip_suffix("==").unwrap_or(map);
+
+        if map.len() != MAP_LEN {
+            return Err(RuleError::MapLength { len: map.len() });
+        }
+
+        let mut transitions = [false; 512];
+
+        for (i, c) in map.chars().enumerate() {
+            let Some(sextet) = BASE64.iter().position(|&b| b as char == c) else {
+                return Err(RuleError::MapInvalidDigit { got: c });
+            };
+
+            // The last digit is padded with 4 zero bits
+            for j in (0..6).filter(|j| 6 * i + j < 512) {
+                transitions[6 * i + j] = sextet & (1 << (5 - j)) != 0;
+            }
+        }
+
+        Ok(Self::from_transitions(transitions))
+    }
+
+    /// Write the rule as a `MAP` rulestring, without padding. Any rule can be written this way.
+    ///
+    /// See: [`RuleSet::from_map`]
+    pub fn to_map(&self) -> String {
+        let mut map = String::from("MAP");
+
+        for sextet in self.transitions().chunks(6) {
+            let i = sextet
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (j, &t)| acc | (t as usize) << (5 - j));
+
+            map.push(BASE64[i] as char);
+        }
+
+        map
+    }
+
+    /// Create a new `RuleSet` out of a rule tree, like the ones of Golly's `.rule` files. Trees of
+    /// 2 states are stored like any other rule, see [`RuleSet::from_transitions`].
+    ///
+    /// See: [`crate::rule_table`]
+    pub fn from_tree(tree: RuleTree) -> Self {
+        if tree.states() == 2 {
+            let transitions = std::array::from_fn(|i| {
+                tree.next(std::array::from_fn(|j| (i >> (8 - j)) as u8 & 1)) == 1
+            });
+
+            return Self::from_transitions(transitions);
+        }
+
+        Self {
+            states: tree.states(),
+            tree: Some(Arc::new(tree)),
+            ..Self::new(0, 0)
+        }
+    }
+
+    /// Look up a rule by one of its [`RULE_NAMES`], ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (_, rule) = RULE_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))?;
+
+        rule.parse().ok()
+    }
+
+    /// The name of the rule in [`RULE_NAMES`], if it has one. The extension of the rule doesn't
+    /// matter, so Life on a torus is still Life.
+    pub fn canonical_name(&self) -> Option<&'static str> {
+        if self.tree.is_some() {
+            return None;
+        }
+
+        let transitions = self.transitions();
+
+        RULE_NAMES.iter().map(|&(name, _)| name).find(|&name| {
+            Self::from_name(name)
+                .is_some_and(|rule| rule.states == self.states && rule.transitions() == transitions)
+        })
+    }
+
+    /// The rule without its extension, written like `Display` does
+    fn rulestring(&self) -> String {
+        if let Some(tree) = &self.tree {
+            return tree.name().to_string();
+        }
+
+        if self.map.is_some() {
+            return self.to_map();
+        }
+
+        let (births, survivals) = match self.hensel {
+            Some(hensel) => (hensel.births, hensel.survivals),
+            None => (
+                totalistic_configs(self.births()),
+                totalistic_configs(self.survivals()),
+            ),
+        };
+
+        let nbhd = match self.nbhd {
+            Neighbourhood::Moore => "",
+            Neighbourhood::VonNeumann => "V",
+            Neighbourhood::Hexagonal => "H",
+        };
+
+        let states = if self.states > 2 {
+            format!("/C{}", self.states)
+        } else {
+            String::new()
+        };
+
+        format!(
+            "B{}/S{}{nbhd}{states}",
+            fmt_configs(&births),
+            fmt_configs(&survivals)
+        )
+    }
+
+    /// Replace the extension of the rule
+    pub fn extended(self, ext: Option<RuleExtension>) -> Self {
+        Self { ext, ..self }
+    }
+
+    /// Turn the rule into a Generations rule with `states` states. Births and survivals only
+    /// count the neighbours in state 1, and live cells which don't survive go through states 2,
+    /// 3 and so on until they die. 2 states is the rule itself.
+    ///
+    /// Like Golly, Generations rules can't have B0.
+    ///
+    /// See: https://conwaylife.com/wiki/Generations
+    pub fn generations(self, states: u16) -> Self {
+        assert!(
+            (2..=MAX_STATES).contains(&states),
+            "Rules have between 2 and {MAX_STATES} states, got {states}"
+        );
+        assert!(
+            states == 2 || !self.transitions()[0],
+            "Generations rules can't have B0"
+        );
+        assert!(self.tree.is_none(), "Rule trees can't be Generations rules");
+
+        Self { states, ..self }
+    }
+
+    /// Number of states a cell can be in, 2 unless this is a Generations rule or a rule tree
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    /// Numbers of neighbours which cause a birth. For isotropic non-totalistic rules, these are
+    /// the numbers of neighbours which cause a birth in every configuration.
     pub fn births(&self) -> u16 {
         ((self.rule & 0x1FF0000) >> 0x10) as u16
     }
 
+    /// Numbers of neighbours which cause a survival. For isotropic non-totalistic rules, these
+    /// are the numbers of neighbours which cause a survival in every configuration.
     pub fn survivals(&self) -> u16 {
         (self.rule & 0x1FF) as u16
     }
 
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.nbhd
+    }
+
+    /// Births and survivals of isotropic non-totalistic rules
+    pub fn hensel(&self) -> Option<&Hensel> {
+        self.hensel.as_ref()
+    }
+
+    /// Transitions of rules which are neither outer totalistic nor isotropic
+    pub fn map(&self) -> Option<&[bool; 512]> {
+        self.map.as_deref()
+    }
+
+    /// The rule tree of rules of more than 2 states loaded from a rule file
+    pub fn tree(&self) -> Option<&RuleTree> {
+        self.tree.as_deref()
+    }
+
     pub fn extension(&self) -> Option<&RuleExtension> {
         self.ext.as_ref()
     }
@@ -127,38 +725,135 @@ impl RuleSet {
     /// it with a certain rule will return the result
     /// of that rule.
     pub fn compute_rules(&self) -> Vec<u16> {
+        Self::lookup(&self.transitions())
+    }
+
+    /// Compute the game rules to step a world with, one for each phase of the rule.
+    ///
+    /// Rules with B0 turn on every cell of an empty background, which a finite world can't
+    /// represent. Like Golly, we get around this by keeping track of the background. Whenever it
+    /// is on, the world stores the complement of the live cells instead, and is stepped with a
+    /// rule that yields complemented cells.
+    ///
+    /// See: [`RuleSet::compute_rules`]
+    pub fn compute_phases(&self) -> Phases {
+        let t = self.transitions();
+
+        // Rules of more than 2 states are stepped a cell at a time, see `Phases::multi_state`
+        if self.states > 2 {
+            let multi_state = match &self.tree {
+                Some(tree) => MultiState::Tree(tree.clone()),
+                None => MultiState::Generations {
+                    transitions: Box::new(t),
+                    states: self.states,
+                },
+            };
+
+            return Phases {
+                tables: vec![Vec::new()],
+                cycle: 0,
+                multi_state: Some(multi_state),
+            };
+        }
+
+        let (b0, s8) = (t[0], t[0x1FF]);
+
+        // Rules for each combination of the background before and after an iteration
+        let on_to_off: [bool; 512] = std::array::from_fn(|i| t[!i & 0x1FF]);
+        let off_to_on: [bool; 512] = std::array::from_fn(|i| !t[i]);
+        let on_to_on: [bool; 512] = std::array::from_fn(|i| !t[!i & 0x1FF]);
+
+        match (b0, s8) {
+            (false, _) => Phases {
+                tables: vec![Self::lookup(&t)],
+                cycle: 0,
+                multi_state: None,
+            },
+            // The background blinks
+            (true, false) => Phases {
+                tables: vec![Self::lookup(&off_to_on), Self::lookup(&on_to_off)],
+                cycle: 0,
+                multi_state: None,
+            },
+            // The background turns on for good
+            (true, true) => Phases {
+                tables: vec![Self::lookup(&off_to_on), Self::lookup(&on_to_on)],
+                cycle: 1,
+                multi_state: None,
+            },
+        }
+    }
+
+    /// The result of every 4x4 cell, see [`RuleSet::compute_rules`]
+    fn lookup(transitions: &[bool; 512]) -> Vec<u16> {
         let mut rules = vec![0; (u16::MAX as usize) + 1];
 
         for cell in 0..=u16::MAX {
-            rules[cell as usize] = self.next(cell);
+            rules[cell as usize] = Self::next(cell, transitions);
         }
 
         rules
     }
 
-    fn next(&self, cell: u16) -> u16 {
+    /// The fate of a cell for each of its 512 possible neighbourhoods.
+    ///
+    /// A neighbourhood is indexed by the 9 cells of its 3x3 square, read row by row from the top
+    /// left, starting from the most significant bit. The cell itself is bit 4.
+    pub fn transitions(&self) -> [bool; 512] {
+        if let Some(map) = &self.map {
+            return **map;
+        }
+
+        let configs = self.hensel.map(|_| Hensel::configurations());
+
+        std::array::from_fn(|i| {
+            let alive = i & 0x10 != 0;
+            let nbhd = neighbours(i);
+
+            match (self.hensel, configs) {
+                (Some(hensel), Some(configs)) => {
+                    let (n, letter) = configs[nbhd as usize];
+                    let configs = if alive {
+                        hensel.survivals
+                    } else {
+                        hensel.births
+                    };
+
+                    configs[n as usize] & (1 << letter) != 0
+                }
+                _ => {
+                    let n = count_bits((nbhd & self.nbhd.mask()) as u16);
+                    let counts = if alive {
+                        self.survivals()
+                    } else {
+                        self.births()
+                    };
+
+                    counts & (1 << n) != 0
+                }
+            }
+        })
+    }
+
+    fn next(cell: u16, transitions: &[bool; 512]) -> u16 {
         let mut res: u16 = 0;
 
         // goes: top right, top left, bot right, bot left
         let shifts = [0, 1, 4, 5];
 
-        let births = self.births();
-        let survivals = self.survivals();
-
         for shift in shifts {
-            let nbhd_mask = NBHD_MASK << shift;
-            let cell_mask = CELL_MASK << shift;
+            let bit = CELL_BIT + shift;
+            let cell_mask = 1 << bit;
 
-            let dead = (cell & cell_mask) == 0;
-            let num_neighbors = count_bits(cell & nbhd_mask);
+            let nbhd = NEIGHBOURS
+                .iter()
+                .fold(0, |acc, &d| acc << 1 | (cell >> (bit + d)) as usize & 1);
 
-            let num_neighbors = 1 << num_neighbors;
+            // Slot the cell itself in the middle of its neighbours
+            let alive = (cell & cell_mask != 0) as usize;
+            let i = (nbhd >> 4) << 5 | alive << 4 | (nbhd & 0xF);
 
-            if dead {
-                if num_neighbors as u16 & births == num_neighbors as u16 {
-                    res |= cell_mask;
-                }
-            } else if num_neighbors as u16 & survivals == num_neighbors as u16 {
+            if transitions[i] {
                 res |= cell_mask;
             }
         }
@@ -167,6 +862,97 @@ impl RuleSet {
     }
 }
 
+/// The game rules of a rule for each of its phases. See [`RuleSet::compute_phases`].
+///
+/// Phase `i` is the phase of generation `i`. Once past the last phase, phases loop back to `cycle`.
+/// Rules have at most 2 phases.
+pub struct Phases {
+    tables: Vec<Vec<u16>>,
+
+    cycle: u8,
+
+    /// Set for rules of more than 2 states, whose leaves hold cell states rather than bits. See
+    /// [`CellBuf`](crate::cell::CellBuf).
+    multi_state: Option<MultiState>,
+}
+
+/// The game rules of a rule of more than 2 states
+pub enum MultiState {
+    /// See [`RuleSet::generations`]
+    Generations {
+        /// Fate of a cell in state 0 or 1, see [`RuleSet::transitions`]
+        transitions: Box<[bool; 512]>,
+
+        states: u16,
+    },
+
+    /// See [`RuleSet::from_tree`]
+    Tree(Arc<RuleTree>),
+}
+
+impl MultiState {
+    /// The state of the center cell of a 3x3 square of cells after an iteration. Cells are read
+    /// row by row from the top left.
+    pub fn next(&self, cells: [u8; 9]) -> u8 {
+        match self {
+            Self::Generations {
+                transitions,
+                states,
+            } => match cells[4] {
+                0 | 1 => {
+                    let i = cells
+                        .iter()
+                        .fold(0, |acc, &state| acc << 1 | (state == 1) as usize);
+
+                    match (cells[4], transitions[i]) {
+                        (_, true) => 1,
+                        (0, false) => 0,
+                        (_, false) => 2,
+                    }
+                }
+                state => ((state as u16 + 1) % states) as u8,
+            },
+            Self::Tree(tree) => tree.next(cells),
+        }
+    }
+}
+
+impl Phases {
+    /// The game rules to use for an iteration from `phase`, see [`RuleSet::compute_rules`]
+    pub fn table(&self, phase: u8) -> &[u16] {
+        &self.tables[phase as usize]
+    }
+
+    /// The game rules of rules of more than 2 states, which are the same for every phase
+    pub fn multi_state(&self) -> Option<&MultiState> {
+        self.multi_state.as_ref()
+    }
+
+    /// The phase of generation `generation`
+    pub fn phase_at(&self, generation: u64) -> u8 {
+        let (len, cycle) = (self.tables.len() as u64, self.cycle as u64);
+
+        if generation < len {
+            generation as u8
+        } else {
+            (cycle + (generation - cycle) % (len - cycle)) as u8
+        }
+    }
+
+    /// The phase `2^j` generations after `phase`.
+    ///
+    /// Since there are at most 2 phases, this is the same for every `j > 0`.
+    pub fn advance(&self, phase: u8, j: u8) -> u8 {
+        self.phase_at(phase as u64 + (1 << j.min(1)))
+    }
+
+    /// Whether the background is on at `phase`. If it is, the world holds the complement of its
+    /// live cells.
+    pub fn background(&self, phase: u8) -> bool {
+        phase > 0
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum RuleError {
     #[error("Parse error: {0}")]
@@ -184,16 +970,69 @@ pub enum RuleError {
     #[error("Some number of survivals is required")]
     NoSurvivalsCount,
 
-    #[error("Birth count should only contain digits")]
+    #[error("Birth count should only contain digits, each optionally followed by Hensel letters")]
     BirthCountContainsNonDigits,
 
-    #[error("Survival count should only contain digits")]
+    #[error(
+        "Survival count should only contain digits, each optionally followed by Hensel letters"
+    )]
     SurvivalCountContainsNonDigits,
 
+    #[error("No configuration of {n} neighbours is named '{letter}' in the births")]
+    InvalidBirthLetter { n: u8, letter: char },
+
+    #[error("No configuration of {n} neighbours is named '{letter}' in the survivals")]
+    InvalidSurvivalLetter { n: u8, letter: char },
+
+    #[error("Hensel letters can only be used with the Moore neighbourhood, not {nbhd:?}")]
+    NonTotalisticNeighbourhood { nbhd: Neighbourhood },
+
+    #[error(
+        "Cells have at most {max} neighbours in the {nbhd:?} neighbourhood, got {n}",
+        max = .nbhd.size()
+    )]
+    TooManyNeighbours { n: u8, nbhd: Neighbourhood },
+
+    #[error("Generations rules have between 2 and {MAX_STATES} states, got '{got}'")]
+    InvalidStates { got: String },
+
+    #[error("Generations rules can't have B0")]
+    GenerationsB0,
+
+    #[error("MAP rules must start with MAP")]
+    NoMap,
+
+    #[error("MAP rules should have {MAP_LEN} base64 digits, got {len}")]
+    MapLength { len: usize },
+
+    #[error("Invalid base64 digit in MAP rule: '{got}'")]
+    MapInvalidDigit { got: char },
+
+    #[error("Unexpected characters after the rule: '{got}'")]
+    TrailingCharacters { got: String },
+
     #[error("Rule extension error: {0}")]
     ExtensionError(#[from] RuleExtensionError),
 }
 
+impl RuleError {
+    fn births(err: ConfigError, nbhd: Neighbourhood) -> Self {
+        match err {
+            ConfigError::NonDigit => Self::BirthCountContainsNonDigits,
+            ConfigError::TooManyNeighbours { n } => Self::TooManyNeighbours { n, nbhd },
+            ConfigError::InvalidLetter { n, letter } => Self::InvalidBirthLetter { n, letter },
+        }
+    }
+
+    fn survivals(err: ConfigError, nbhd: Neighbourhood) -> Self {
+        match err {
+            ConfigError::NonDigit => Self::SurvivalCountContainsNonDigits,
+            ConfigError::TooManyNeighbours { n } => Self::TooManyNeighbours { n, nbhd },
+            ConfigError::InvalidLetter { n, letter } => Self::InvalidSurvivalLetter { n, letter },
+        }
+    }
+}
+
 #[inline]
 fn survival_stop_fn(b: u8) -> bool {
     b.is_ascii_whitespace() ||
@@ -202,8 +1041,22 @@ fn survival_stop_fn(b: u8) -> bool {
     b == b':'
 }
 
-// Parse rules that look like b3/s23
+// Parse rules that look like b3/s23, MAP rules, or named rules like HighLife
 pub(crate) fn parse_rule(bytes: &[u8]) -> Result<(RuleSet, &[u8]), RuleError> {
+    if bytes.starts_with(b"MAP") {
+        return parse_map_rule(bytes);
+    }
+
+    if let (Some(name), rest) = parse_util::take_until_fn(survival_stop_fn, bytes)
+        && let Some(rule) = RuleSet::from_name(&String::from_utf8_lossy(name))
+    {
+        return parse_extended_rule(rule, rest);
+    }
+
+    if let Some(b's' | b'S') = parse_util::peek_1(bytes) {
+        return parse_sb_rule(bytes);
+    }
+
     let (Some(b'b' | b'B'), bytes) = parse_util::take_1(bytes) else {
         return Err(RuleError::NoBirths);
     };
@@ -211,70 +1064,253 @@ pub(crate) fn parse_rule(bytes: &[u8]) -> Result<(RuleSet, &[u8]), RuleError> {
     let (Some(b), bytes) = parse_util::take_until(b'/', bytes) else {
         return Err(RuleError::NoBirthsCount);
     };
-    let b = bytes_to_num(b).map_err(|_| RuleError::BirthCountContainsNonDigits)?;
-
     let bytes = parse_util::expect(b'/', bytes)?;
 
     let (Some(b's' | b'S'), bytes) = parse_util::take_1(bytes) else {
         return Err(RuleError::NoSurvivals);
     };
 
-    let (Some(s), bytes) = parse_util::take_until_fn(survival_stop_fn, bytes) else {
-        return Err(RuleError::NoSurvivalsCount);
+    let (s, bytes) = match parse_util::take_until_fn(survival_stop_fn, bytes) {
+        (Some(s), bytes) => (s, bytes),
+        // Rules without survivals, like b2/s
+        (None, bytes) if bytes.first().is_some_and(|&b| survival_stop_fn(b)) => (&[][..], bytes),
+        (None, _) => return Err(RuleError::NoSurvivalsCount),
+    };
+
+    // Generations rules look like b2/s/c3
+    let rule = match s.iter().position(|&b| b == b'/') {
+        Some(i) => parse_generations(build_rule(b, &s[..i])?, &s[i + 1..])?,
+        None => build_rule(b, s)?,
+    };
+
+    parse_extended_rule(rule, bytes)
+}
+
+// Parse rules that look like s23/b3, where the neighbourhood and number of states still come last,
+// like in s23/b3v/c3
+fn parse_sb_rule(bytes: &[u8]) -> Result<(RuleSet, &[u8]), RuleError> {
+    let (Some(b's' | b'S'), bytes) = parse_util::take_1(bytes) else {
+        return Err(RuleError::NoSurvivals);
+    };
+
+    let (s, bytes) = match parse_util::take_until(b'/', bytes) {
+        (Some(s), bytes) => (s, bytes),
+        // Rules without survivals, like s/b2
+        (None, bytes) if bytes.starts_with(b"/") => (&[][..], bytes),
+        (None, _) => return Err(RuleError::NoSurvivalsCount),
+    };
+
+    let bytes = parse_util::expect(b'/', bytes)?;
+
+    let (Some(b'b' | b'B'), bytes) = parse_util::take_1(bytes) else {
+        return Err(RuleError::NoBirths);
+    };
+
+    let (Some(b), bytes) = parse_util::take_until_fn(survival_stop_fn, bytes) else {
+        return Err(RuleError::NoBirthsCount);
     };
-    let s = bytes_to_num(s).map_err(|_| RuleError::SurvivalCountContainsNonDigits)?;
 
+    let (b, states) = match b.iter().position(|&b| b == b'/') {
+        Some(i) => (&b[..i], Some(&b[i + 1..])),
+        None => (b, None),
+    };
+
+    // Move the neighbourhood over to the survivals, see `build_rule`
+    let (b, nbhd) = match b.split_last() {
+        Some((b'V' | b'v' | b'H' | b'h', rest)) => b.split_at(rest.len()),
+        _ => (b, &[][..]),
+    };
+
+    let rule = build_rule(b, &[s, nbhd].concat())?;
+
+    let rule = match states {
+        Some(states) => parse_generations(rule, states)?,
+        None => rule,
+    };
+
+    parse_extended_rule(rule, bytes)
+}
+
+// Parse rules that look like MAPARYXfhZofugWaH7oaIDogBZofuhogOiAaIDogIAAgAAWaH7oaIDogGiA6ICAAIAAaIDogIAAgACAAIAAAAAAAA
+fn parse_map_rule(bytes: &[u8]) -> Result<(RuleSet, &[u8]), RuleError> {
+    let (Some(map), bytes) = parse_util::take_until_fn(survival_stop_fn, bytes) else {
+        return Err(RuleError::NoMap);
+    };
+
+    let rule = RuleSet::from_map(&String::from_utf8_lossy(map))?;
+
+    parse_extended_rule(rule, bytes)
+}
+
+/// Build a rule out of its births and survivals, where the survivals may end with a
+/// neighbourhood: `V` for von Neumann or `H` for hexagonal
+fn build_rule(b: &[u8], s: &[u8]) -> Result<RuleSet, RuleError> {
+    let (nbhd, s) = match s.split_last() {
+        Some((b'V' | b'v', s)) => (Neighbourhood::VonNeumann, s),
+        Some((b'H' | b'h', s)) => (Neighbourhood::Hexagonal, s),
+        _ => (Neighbourhood::Moore, s),
+    };
+
+    let b = parse_configs(b).map_err(|err| RuleError::births(err, nbhd))?;
+    let s = parse_configs(s).map_err(|err| RuleError::survivals(err, nbhd))?;
+
+    let rule = RuleSet::isotropic(Hensel {
+        births: b,
+        survivals: s,
+    });
+
+    if nbhd == Neighbourhood::Moore {
+        return Ok(rule);
+    }
+
+    if rule.hensel().is_some() {
+        return Err(RuleError::NonTotalisticNeighbourhood { nbhd });
+    }
+
+    let (b, s) = (rule.births(), rule.survivals());
+
+    if let Some(n) = (nbhd.size() + 1..9).find(|n| (b | s) & (1 << n) != 0) {
+        return Err(RuleError::TooManyNeighbours { n, nbhd });
+    }
+
+    Ok(RuleSet::with_neighbourhood(b, s, nbhd))
+}
+
+/// Parse the extension following `rule`, if there is one
+fn parse_extended_rule(rule: RuleSet, bytes: &[u8]) -> Result<(RuleSet, &[u8]), RuleError> {
     let (rule, bytes) = if let Some(b':') = parse_util::peek_1(bytes) {
         let (ext, bytes) = parse_rule_extension(bytes)?;
 
-        (RuleSet::with_extension(b, s, ext), bytes)
+        (rule.extended(Some(ext)), bytes)
     } else {
-        (RuleSet::new(b, s), bytes)
+        (rule, bytes)
     };
 
     Ok((rule, bytes))
 }
 
 // Parse rules that look like 3/23. These show up in RLE #r comment lines.
+//
+// Generations rules are written survivals first, like Golly does: /2/3 is b2/s/c3.
 pub(crate) fn parse_nameless_rule(bytes: &[u8]) -> Result<(RuleSet, &[u8]), RuleError> {
-    let (Some(b), bytes) = parse_util::take_until(b'/', bytes) else {
-        return Err(RuleError::NoBirthsCount);
+    let (first, bytes) = match parse_util::take_until(b'/', bytes) {
+        (Some(first), bytes) => (first, bytes),
+        // Generations rules without survivals
+        (None, bytes) if bytes.starts_with(b"/") => (&[][..], bytes),
+        (None, _) => return Err(RuleError::NoBirthsCount),
     };
-    let b = bytes_to_num(b).map_err(|_| RuleError::BirthCountContainsNonDigits)?;
 
     let bytes = parse_util::expect(b'/', bytes)?;
 
-    let (Some(s), bytes) = parse_util::take_until_fn(survival_stop_fn, bytes) else {
-        return Err(RuleError::NoSurvivalsCount);
+    let (second, bytes) = match parse_util::take_until_fn(survival_stop_fn, bytes) {
+        (Some(second), bytes) => (second, bytes),
+        // Rules without survivals, like 2/
+        (None, bytes) if bytes.first().is_some_and(|&b| survival_stop_fn(b)) => (&[][..], bytes),
+        (None, _) => return Err(RuleError::NoSurvivalsCount),
     };
-    let s = bytes_to_num(s).map_err(|_| RuleError::SurvivalCountContainsNonDigits)?;
 
-    let (rule, bytes) = if let Some(b':') = parse_util::peek_1(bytes) {
-        let (ext, bytes) = parse_rule_extension(bytes)?;
-
-        (RuleSet::with_extension(b, s, ext), bytes)
-    } else {
-        (RuleSet::new(b, s), bytes)
+    let rule = match second.iter().position(|&b| b == b'/') {
+        Some(i) => parse_generations(build_rule(&second[..i], first)?, &second[i + 1..])?,
+        None => build_rule(first, second)?,
     };
 
-    Ok((rule, bytes))
+    parse_extended_rule(rule, bytes)
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Turn `rule` into a Generations rule, with its number of states given like `c3`, `g3` or `3`
+fn parse_generations(rule: RuleSet, bytes: &[u8]) -> Result<RuleSet, RuleError> {
+    let digits = match bytes.split_first() {
+        Some((b'C' | b'c' | b'G' | b'g', digits)) => digits,
+        _ => bytes,
+    };
+
+    let states = parse_util::convert::<u16>(digits)
+        .ok()
+        .filter(|states| (2..=MAX_STATES).contains(states))
+        .ok_or_else(|| RuleError::InvalidStates {
+            got: String::from_utf8_lossy(bytes).into_owned(),
+        })?;
+
+    if states > 2 && rule.transitions()[0] {
+        return Err(RuleError::GenerationsB0);
+    }
+
+    Ok(rule.generations(states))
+}
+/// How the edges of a bounded grid are glued together, following Golly.
+///
+/// A cylinder is a torus with one of its dimensions set to 0, like `T30,0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RuleTopology {
+    /// Nothing lies past the edges, `P`
     Planar,
+    /// Opposite edges are joined, `T`
     Torus,
+    /// Opposite edges are joined, with one pair twisted, `K`
     KleinBottle,
+    /// The top edge is joined to the left edge and the bottom edge to the right edge, `S`
     Spherical,
-    Cylindrical,
+    /// Opposite edges are joined, with both pairs twisted, `C`
+    CrossSurface,
+}
+
+/// A pair of opposite edges of a bounded grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edges {
+    /// The top and bottom edges, marked after the width
+    Horizontal,
+    /// The left and right edges, marked after the height
+    Vertical,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RuleExtension {
     pub topology: RuleTopology,
     pub width: u32,
     pub height: u32,
-    pub generation: u64,
+    /// The pair of edges joined with a twist on a Klein bottle, marked with `*`
+    pub twist: Option<Edges>,
+    /// A pair of edges of a torus or Klein bottle that is joined with a shift, marked with `+n`.
+    ///
+    /// Like in Golly, crossing the bottom edge comes back in from the top edge `n` columns to the
+    /// right, and crossing the right edge comes back in from the left edge `n` rows lower.
+    pub shift: Option<(Edges, u32)>,
+}
+
+/// Write the extension like it comes after a rule, like `:T100+5,50`
+impl std::fmt::Display for RuleExtension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let letter = match self.topology {
+            RuleTopology::Planar => 'P',
+            RuleTopology::Torus => 'T',
+            RuleTopology::KleinBottle => 'K',
+            RuleTopology::Spherical => 'S',
+            RuleTopology::CrossSurface => 'C',
+        };
+
+        let edge = |f: &mut std::fmt::Formatter<'_>, size: u32, edges: Edges| {
+            write!(f, "{size}")?;
+
+            if self.twist == Some(edges) {
+                write!(f, "*")?;
+            }
+
+            match self.shift {
+                Some((shifted, n)) if shifted == edges => write!(f, "+{n}"),
+                _ => Ok(()),
+            }
+        };
+
+        write!(f, ":{letter}")?;
+        edge(f, self.width, Edges::Horizontal)?;
+
+        if let RuleTopology::Spherical = self.topology {
+            return Ok(());
+        }
+
+        write!(f, ",")?;
+        edge(f, self.height, Edges::Vertical)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -294,17 +1330,32 @@ pub enum RuleExtensionError {
     #[error("Height undefined")]
     NoHeight,
 
-    #[error("Generation undefined")]
-    NoGeneration,
+    #[error("Shift undefined")]
+    NoShift,
+
+    #[error("Failed to parse size: {0}")]
+    ParseSize(#[from] parse_util::ConvertError),
+
+    #[error("Only a Klein bottle can have twisted edges, got: '{got:?}'")]
+    UnexpectedTwist { got: RuleTopology },
+
+    #[error("A Klein bottle should have exactly one pair of twisted edges")]
+    TwistCount,
 
-    #[error("Failed to parse width. Should be either <number> or *, got: {got}")]
-    ParseWidth { got: String },
+    #[error("Only a torus or a Klein bottle can have shifted edges, got: '{got:?}'")]
+    UnexpectedShift { got: RuleTopology },
 
-    #[error("Failed to parse height. Should be either <number> or *, got: {got}")]
-    ParseHeight { got: String },
+    #[error("Only one pair of edges can be shifted")]
+    ShiftCount,
 
-    #[error("Failed to parse generation: {0}")]
-    ParseGeneration(#[from] parse_util::ConvertError),
+    #[error("Only the twisted edges of a Klein bottle can be shifted")]
+    ShiftNotTwisted,
+
+    #[error("Shifted edges need both dimensions to be bounded")]
+    ShiftUnbounded,
+
+    #[error("A {topology:?} grid can't have a dimension of size 0")]
+    UnboundedDimension { topology: RuleTopology },
 }
 
 pub(crate) fn parse_rule_extension(
@@ -325,148 +1376,191 @@ pub(crate) fn parse_rule_extension(
 
         b'S' => RuleTopology::Spherical,
 
-        b'C' => RuleTopology::Cylindrical,
+        b'C' => RuleTopology::CrossSurface,
 
         b => return Err(RuleExtensionError::UnrecognizedTopology { got: b as char }),
     };
 
-    let height_take_fn = |b: u8| -> bool {
-        b.is_ascii_whitespace()
-
-        // Include generation
-        || b == b'+'
+    let (width, width_twist, width_shift, bytes) = match parse_rule_extension_edge(bytes) {
+        Err(RuleExtensionError::UnexpectedEof) => return Err(RuleExtensionError::NoWidth),
+        edge => edge?,
     };
 
-    match topology {
-        RuleTopology::Planar | RuleTopology::Torus | RuleTopology::Cylindrical => {
-            let (Some(width_bs), bytes) = parse_util::take_until(b',', bytes) else {
-                return Err(RuleExtensionError::NoWidth);
-            };
-            let width = parse_util::convert(width_bs)?;
-
+    // A sphere is square, so its only dimension is both the width and the height
+    let (height, height_twist, height_shift, bytes) = match topology {
+        RuleTopology::Spherical => (width, false, None, bytes),
+        _ => {
             let bytes = parse_util::expect(b',', bytes)?;
 
-            let (Some(height_bs), bytes) = parse_util::take_until_fn(height_take_fn, bytes) else {
-                return Err(RuleExtensionError::NoHeight);
-            };
-            let height = parse_util::convert(height_bs)?;
-
-            let (generation, bytes) = parse_rule_extension_generation(bytes)?;
-
-            let extension = RuleExtension {
-                topology,
-                width,
-                height,
-                generation,
-            };
-
-            Ok((extension, bytes))
+            match parse_rule_extension_edge(bytes) {
+                Err(RuleExtensionError::UnexpectedEof) => return Err(RuleExtensionError::NoHeight),
+                edge => edge?,
+            }
         }
-        RuleTopology::Spherical => {
-            let (Some(size_bs), bytes) = parse_util::take_until_fn(height_take_fn, bytes) else {
-                return Err(RuleExtensionError::NoHeight);
-            };
+    };
 
-            let size = parse_util::convert(size_bs)?;
+    let twist = match (width_twist, height_twist) {
+        (false, false) => None,
+        (true, false) => Some(Edges::Horizontal),
+        (false, true) => Some(Edges::Vertical),
+        (true, true) => return Err(RuleExtensionError::TwistCount),
+    };
 
-            let (generation, bytes) = parse_rule_extension_generation(bytes)?;
+    let shift = match (width_shift, height_shift) {
+        (None, None) => None,
+        (Some(n), None) => Some((Edges::Horizontal, n)),
+        (None, Some(n)) => Some((Edges::Vertical, n)),
+        (Some(_), Some(_)) => return Err(RuleExtensionError::ShiftCount),
+    };
 
-            let extension = RuleExtension {
-                topology,
-                width: size,
-                height: size,
-                generation,
-            };
+    match topology {
+        RuleTopology::KleinBottle if twist.is_none() => return Err(RuleExtensionError::TwistCount),
+        RuleTopology::KleinBottle => {}
+        got if twist.is_some() => return Err(RuleExtensionError::UnexpectedTwist { got }),
+        _ => {}
+    }
 
-            Ok((extension, bytes))
+    if let Some((edges, _)) = shift {
+        match topology {
+            RuleTopology::Torus => {}
+            RuleTopology::KleinBottle if twist != Some(edges) => {
+                return Err(RuleExtensionError::ShiftNotTwisted);
+            }
+            RuleTopology::KleinBottle => {}
+            got => return Err(RuleExtensionError::UnexpectedShift { got }),
         }
-        RuleTopology::KleinBottle => {
-            let width_take_fn = |b: u8| -> bool { b == b',' || b == b'*' };
-            let (Some(width_bs), bytes) = parse_util::take_until_fn(width_take_fn, bytes) else {
-                return Err(RuleExtensionError::NoWidth);
-            };
-            let width = parse_util::convert(width_bs)?;
-
-            // TODO: We parse which axis is flipped but don't keep track of it
-            let (_flip, bytes) = if let Some(b'*') = parse_util::peek_1(bytes) {
-                let Ok(bytes) = parse_util::expect(b'*', bytes) else {
-                    unreachable!("We peeked and saw b'*'")
-                };
 
-                (true, bytes)
-            } else {
-                (false, bytes)
-            };
+        if width == 0 || height == 0 {
+            return Err(RuleExtensionError::ShiftUnbounded);
+        }
+    }
 
-            let bytes = parse_util::expect(b',', bytes)?;
+    let unbounded = width == 0 || height == 0;
+    if unbounded && !matches!(topology, RuleTopology::Planar | RuleTopology::Torus) {
+        return Err(RuleExtensionError::UnboundedDimension { topology });
+    }
 
-            let height_take_fn =
-                |b: u8| -> bool { b == b'*' || b == b'+' || b.is_ascii_whitespace() };
-            let (Some(height_bs), bytes) = parse_util::take_until_fn(height_take_fn, bytes) else {
-                return Err(RuleExtensionError::NoWidth);
-            };
-            let height = parse_util::convert(height_bs)?;
+    let extension = RuleExtension {
+        topology,
+        width,
+        height,
+        twist,
+        shift,
+    };
 
-            // TODO: We parse which axis is flipped but don't keep track of it
-            let (_flip, bytes) = if let Some(b'*') = parse_util::peek_1(bytes) {
-                let Ok(bytes) = parse_util::expect(b'*', bytes) else {
-                    unreachable!("We peeked and saw b'*'")
-                };
+    Ok((extension, bytes))
+}
 
-                (true, bytes)
-            } else {
-                (false, bytes)
-            };
+/// Parse the size of one dimension of the grid, along with whether the edges it spans are
+/// twisted or shifted, like `100*+2`
+#[allow(clippy::type_complexity)]
+fn parse_rule_extension_edge(
+    bytes: &[u8],
+) -> Result<(u32, bool, Option<u32>, &[u8]), RuleExtensionError> {
+    let is_end = |b: u8| -> bool { !b.is_ascii_digit() };
 
-            let (generation, bytes) = parse_rule_extension_generation(bytes)?;
+    let (Some(size_bs), bytes) = parse_util::take_until_fn(is_end, bytes) else {
+        return Err(RuleExtensionError::UnexpectedEof);
+    };
+    let size = parse_util::convert(size_bs)?;
 
-            let extension = RuleExtension {
-                topology,
-                width,
-                height,
-                generation,
-            };
+    let (twist, bytes) = if let Some(b'*') = parse_util::peek_1(bytes) {
+        let Ok(bytes) = parse_util::expect(b'*', bytes) else {
+            unreachable!("We peeked and saw b'*'")
+        };
 
-            Ok((extension, bytes))
-        }
-    }
-}
+        (true, bytes)
+    } else {
+        (false, bytes)
+    };
 
-fn parse_rule_extension_generation(bytes: &[u8]) -> Result<(u64, &[u8]), RuleExtensionError> {
-    if let Some(b'+') = parse_util::peek_1(bytes) {
+    let (shift, bytes) = if let Some(b'+') = parse_util::peek_1(bytes) {
         let Ok(bytes) = parse_util::expect(b'+', bytes) else {
             unreachable!("We peeked and saw b'+'")
         };
 
-        let (Some(gen_bs), bytes) = parse_util::take_until_ws(bytes) else {
-            return Err(RuleExtensionError::NoGeneration);
+        let (Some(shift_bs), bytes) = parse_util::take_until_fn(is_end, bytes) else {
+            return Err(RuleExtensionError::NoShift);
         };
 
-        let generation = parse_util::convert(gen_bs)?;
-
-        Ok((generation, bytes))
+        (Some(parse_util::convert(shift_bs)?), bytes)
     } else {
-        Ok((0, bytes))
-    }
+        (None, bytes)
+    };
+
+    Ok((size, twist, shift, bytes))
 }
 
-/// Convert the human readable birth/survival number to a packed bit representation
-fn bytes_to_num(bytes: &[u8]) -> Result<u16, ()> {
-    let mut n = 0;
+enum ConfigError {
+    NonDigit,
+    TooManyNeighbours { n: u8 },
+    InvalidLetter { n: u8, letter: char },
+}
 
-    for &b in bytes {
+/// Parse births or survivals in Hensel notation, like `2-a3ei`, into the configurations of each
+/// number of neighbours. See [`Hensel`].
+///
+/// A number on its own stands for every configuration. When followed by letters, it only stands
+/// for the configurations they name, or every other configuration if the letters come after a
+/// `-`.
+fn parse_configs(bytes: &[u8]) -> Result<[u16; 9], ConfigError> {
+    let mut configs = [0; 9];
+    let mut bytes = bytes;
+
+    while let Some((&b, rest)) = bytes.split_first() {
         if !b.is_ascii_digit() {
-            return Err(());
+            return Err(ConfigError::NonDigit);
+        }
+
+        if b == b'9' {
+            return Err(ConfigError::TooManyNeighbours { n: 9 });
+        }
+
+        let n = b - b'0';
+
+        let (negate, rest) = match rest.split_first() {
+            Some((b'-', rest)) => (true, rest),
+            _ => (false, rest),
+        };
+
+        let len = rest
+            .iter()
+            .position(|b| !b.is_ascii_lowercase())
+            .unwrap_or(rest.len());
+        let (letters, rest) = rest.split_at(len);
+
+        let mut c = 0;
+        for &letter in letters {
+            let i = HENSEL_LETTERS[..HENSEL_CONFIGS[n as usize] as usize]
+                .iter()
+                .position(|&l| l == letter)
+                .filter(|_| (1..8).contains(&n))
+                .ok_or(ConfigError::InvalidLetter {
+                    n,
+                    letter: letter as char,
+                })?;
+
+            c |= 1 << i;
         }
 
-        n |= 1 << (b - b'0');
+        let all = Hensel::all(n as usize);
+
+        configs[n as usize] |= match (negate, c) {
+            (false, 0) => all,
+            (false, c) => c,
+            (true, c) => all & !c,
+        };
+
+        bytes = rest;
     }
 
-    Ok(n)
+    Ok(configs)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use crate::rule_set::RuleError;
 
     #[test]
@@ -484,7 +1578,8 @@ mod tests {
                     topology: Torus,
                     width: 100,
                     height: 58,
-                    generation: 0,
+                    twist: None,
+                    shift: None,
                 },
             ),
         }
@@ -495,7 +1590,8 @@ mod tests {
     }
 
     #[test]
-    fn test_rule_with_extension_and_generation() -> Result<(), RuleError> {
+    /// A shift comes after the dimension spanning the shifted edges
+    fn test_rule_with_shifted_extension() -> Result<(), RuleError> {
         // NOTE: final whitespace needed since RLE files never end in a rule
         let rule_bs = b"B3/S23:T100,58+4 ";
 
@@ -509,7 +1605,13 @@ mod tests {
                     topology: Torus,
                     width: 100,
                     height: 58,
-                    generation: 4,
+                    twist: None,
+                    shift: Some(
+                        (
+                            Vertical,
+                            4,
+                        ),
+                    ),
                 },
             ),
         }
@@ -535,7 +1637,10 @@ mod tests {
                     topology: KleinBottle,
                     width: 100,
                     height: 100,
-                    generation: 0,
+                    twist: Some(
+                        Horizontal,
+                    ),
+                    shift: None,
                 },
             ),
         }
@@ -544,4 +1649,249 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    /// Every configuration of up to 4 neighbours is named by exactly one letter
+    fn test_hensel_neighbourhoods() {
+        let binomials = [1, 8, 28, 56, 70];
+
+        for (n, neighbourhoods) in super::HENSEL_NEIGHBOURHOODS.iter().enumerate() {
+            let mut seen = HashSet::new();
+
+            for &nbhd in neighbourhoods.iter() {
+                let orbit: HashSet<u8> = super::symmetries(nbhd).into_iter().collect();
+
+                assert!(orbit.iter().all(|c| c.count_ones() == n as u32));
+                assert!(seen.is_disjoint(&orbit), "{nbhd:#010b} is named twice");
+
+                seen.extend(orbit);
+            }
+
+            assert_eq!(seen.len(), binomials[n]);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::unusual_byte_groupings)]
+    fn test_isotropic_rule() -> Result<(), RuleError> {
+        let (rule, _) = super::parse_rule(b"B2-a3ei/S12 ")?;
+
+        insta::assert_debug_snapshot!(rule, @r#"
+        RuleSet {
+            rule: "b2-a3ei/s12",
+            ext: None,
+        }
+        "#);
+
+        // Births from 2 adjacent neighbours are excluded, births from 2 opposite edges aren't
+        let transitions = rule.transitions();
+        assert!(!transitions[0b110_000_000]);
+        assert!(transitions[0b010_000_010]);
+
+        // Listing every letter is the same as listing none
+        let (rule, _) = super::parse_rule(b"B3/S2cekain3 ")?;
+        assert!(rule.hensel().is_none());
+        assert_eq!(rule.survivals(), 0b1100);
+
+        assert!(matches!(
+            super::parse_rule(b"B3/S2z "),
+            Err(RuleError::InvalidSurvivalLetter { n: 2, letter: 'z' })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_rule() -> Result<(), RuleError> {
+        let life = "MAPARYXfhZofugWaH7oaIDogBZofuhogOiAaIDogIAAgAAWaH7oaIDogGiA6ICAAIAAaIDogIAAgACAAIAAAAAAAA";
+
+        assert_eq!(super::B3S23.to_map(), life);
+
+        // MAP rules that turn out to be outer totalistic are stored as such
+        let padded = format!("{life}== ");
+        let (rule, bs) = super::parse_rule(padded.as_bytes())?;
+        assert!(rule.map().is_none());
+        assert_eq!((rule.births(), rule.survivals()), (0b1000, 0b1100));
+        assert_eq!(bs, b" ");
+
+        // Births only from a single neighbour to the north
+        let mut transitions = [false; 512];
+        transitions[0b010_000_000] = true;
+
+        let rule = super::RuleSet::from_transitions(transitions);
+        assert_eq!(
+            super::RuleSet::from_map(&rule.to_map())?.map(),
+            Some(&transitions)
+        );
+
+        insta::assert_debug_snapshot!(rule, @r#"
+        RuleSet {
+            rule: "MAPAAAAAAAAAAAAAAAAAAAAAIAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+            ext: None,
+        }
+        "#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_neighbourhood_rule() -> Result<(), RuleError> {
+        let (rule, _) = super::parse_rule(b"B2/S34H ")?;
+
+        insta::assert_debug_snapshot!(rule, @r#"
+        RuleSet {
+            rule: "b2/s34h",
+            ext: None,
+        }
+        "#);
+
+        // The NE and SW neighbours don't count
+        let transitions = rule.transitions();
+        assert!(transitions[0b110_000_000]);
+        assert!(!transitions[0b011_000_000]);
+
+        let (rule, _) = super::parse_rule(b"B1/S1V ")?;
+        assert_eq!(rule.neighbourhood(), super::Neighbourhood::VonNeumann);
+
+        let transitions = rule.transitions();
+        assert!(transitions[0b010_000_000]);
+        assert!(!transitions[0b100_000_000]);
+
+        assert!(matches!(
+            super::parse_rule(b"B2/S5V "),
+            Err(RuleError::TooManyNeighbours { n: 5, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generations_rule() -> Result<(), RuleError> {
+        let (rule, _) = super::parse_rule(b"B2/S/C3 ")?;
+
+        insta::assert_debug_snapshot!(rule, @r#"
+        RuleSet {
+            rule: "b2/s/c3",
+            ext: None,
+        }
+        "#);
+
+        // Survivals come first without a name
+        let (rule, _) = super::parse_nameless_rule(b"/2/3 ")?;
+        assert_eq!(
+            (rule.births(), rule.survivals(), rule.states()),
+            (0b100, 0, 3)
+        );
+
+        let (rule, _) = super::parse_nameless_rule(b"345/2/4 ")?;
+        assert_eq!(
+            (rule.births(), rule.survivals(), rule.states()),
+            (0b100, 0b111000, 4)
+        );
+
+        assert!(matches!(
+            super::parse_rule(b"B02/S/C3 "),
+            Err(RuleError::GenerationsB0)
+        ));
+        assert!(matches!(
+            super::parse_rule(b"B2/S/C1 "),
+            Err(RuleError::InvalidStates { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_rule() -> Result<(), RuleError> {
+        let (rule, _) = super::parse_rule(b"highlife:T10,10 ")?;
+
+        insta::assert_debug_snapshot!(rule, @r#"
+        RuleSet {
+            rule: "b36/s23",
+            ext: Some(
+                RuleExtension {
+                    topology: Torus,
+                    width: 10,
+                    height: 10,
+                    twist: None,
+                    shift: None,
+                },
+            ),
+        }
+        "#);
+        assert_eq!(rule.canonical_name(), Some("HighLife"));
+
+        let (rule, _) = super::parse_rule(b"2x2 ")?;
+        assert_eq!((rule.births(), rule.survivals()), (0b1001000, 0b100110));
+
+        // Rules without survivals
+        let (rule, _) = super::parse_rule(b"B2/S ")?;
+        assert_eq!(rule.canonical_name(), Some("Seeds"));
+
+        for &(name, _) in super::RULE_NAMES {
+            let rule = super::RuleSet::from_name(name).unwrap();
+            assert_eq!(rule.canonical_name(), Some(name));
+        }
+
+        assert_eq!(super::RuleSet::new(0b1000, 0b10).canonical_name(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_display() -> Result<(), RuleError> {
+        let rules = [
+            ("b3/s23", "B3/S23"),
+            ("S23/B36", "B36/S23"),
+            ("3/23:T100,50", "B3/S23:T100,50"),
+            ("B3/S23:K100*+4,58", "B3/S23:K100*+4,58"),
+            ("B3/S23:K30,20*", "B3/S23:K30,20*"),
+            ("B3/S23:T30,20+5", "B3/S23:T30,20+5"),
+            ("B2-a/S12", "B2-a/S12"),
+            ("s1/b1v:S20", "B1/S1V:S20"),
+            ("S345/B2/C4", "B2/S345/C4"),
+            ("/2/3", "B2/S/C3"),
+            ("Seeds", "B2/S"),
+            ("  HighLife:C10,20  ", "B36/S23:C10,20"),
+        ];
+
+        for (s, canonical) in rules {
+            let rule: super::RuleSet = s.parse()?;
+
+            assert_eq!(rule.to_string(), canonical);
+            assert_eq!(canonical.parse::<super::RuleSet>()?.to_string(), canonical);
+        }
+
+        let map = "MAPARYXfhZofugWaH7oaIDogBZofuhogOiAaIDogIAAgAAWaH7oaIDogGiA6ICAAIAAaIDogIAAgACAAIAAAAAAAA";
+        assert_eq!(map.parse::<super::RuleSet>()?.to_string(), "B3/S23");
+
+        let errors = [
+            "B3/S2x",
+            "B3x/S23",
+            "B3/S23:X10,10",
+            "B3/S23:K10,10",
+            "B3/S23:T10*,10",
+            "B3/S23:K10*,10+2",
+            "B3/S23:C10,0",
+            "B3/S23 extra",
+            "B3/S9V",
+        ];
+        let errors = errors.map(|s| s.parse::<super::RuleSet>().unwrap_err().to_string());
+
+        insta::assert_debug_snapshot!(errors, @r#"
+        [
+            "No configuration of 2 neighbours is named 'x' in the survivals",
+            "No configuration of 3 neighbours is named 'x' in the births",
+            "Rule extension error: Unrecognized topology: 'X'",
+            "Rule extension error: A Klein bottle should have exactly one pair of twisted edges",
+            "Rule extension error: Only a Klein bottle can have twisted edges, got: 'Torus'",
+            "Rule extension error: Only the twisted edges of a Klein bottle can be shifted",
+            "Rule extension error: A CrossSurface grid can't have a dimension of size 0",
+            "Unexpected characters after the rule: 'extra'",
+            "Cells have at most 4 neighbours in the VonNeumann neighbourhood, got 9",
+        ]
+        "#);
+
+        Ok(())
+    }
 }
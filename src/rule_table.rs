@@ -0,0 +1,996 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::rule_set::MAX_STATES;
+use crate::rule_set::RuleSet;
+
+/// Cells of a 3x3 square in the order rule trees visit them: NW, NE, SW, SE, N, W, E, S, then the
+/// cell itself. Cells are numbered row by row from the top left.
+const MOORE_ORDER: [usize; 9] = [0, 2, 6, 8, 1, 3, 5, 7, 4];
+
+/// Like [`MOORE_ORDER`], for trees of 4 neighbours: N, W, E, S, then the cell itself
+const VON_NEUMANN_ORDER: [usize; 5] = [1, 3, 5, 7, 4];
+
+/// The neighbours of a cell in the order rule tables list them, clockwise from the north
+const MOORE_CLOCKWISE: [usize; 8] = [1, 2, 5, 8, 7, 6, 3, 0];
+
+const VON_NEUMANN_CLOCKWISE: [usize; 4] = [1, 5, 7, 3];
+
+/// Like the [`Neighbourhood::Hexagonal`] rules, the NE and SW cells are left out
+///
+/// [`Neighbourhood::Hexagonal`]: crate::rule_set::Neighbourhood::Hexagonal
+const HEXAGONAL_CLOCKWISE: [usize; 6] = [1, 5, 8, 7, 3, 0];
+
+/// Position of the cell itself in a 3x3 square
+const CENTER: usize = 4;
+
+#[derive(Debug, Error)]
+pub enum RuleTableError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Rule files must have a @RULE line")]
+    NoRule,
+
+    #[error("Rule files must have a @TABLE or @TREE section")]
+    NoTable,
+
+    #[error("Line {line}: {header} should come first")]
+    MissingHeader { line: usize, header: &'static str },
+
+    #[error("Line {line}: unrecognized line '{got}'")]
+    InvalidLine { line: usize, got: String },
+
+    #[error("Line {line}: rules have between 2 and {MAX_STATES} states, got '{got}'")]
+    InvalidStates { line: usize, got: String },
+
+    #[error("Line {line}: unknown neighbourhood '{got}'")]
+    UnknownNeighbourhood { line: usize, got: String },
+
+    #[error("Line {line}: unknown symmetries '{got}'")]
+    UnknownSymmetries { line: usize, got: String },
+
+    #[error("Line {line}: invalid variable definition '{got}'")]
+    InvalidVariable { line: usize, got: String },
+
+    #[error("Line {line}: '{got}' is neither a state nor a variable")]
+    InvalidState { line: usize, got: String },
+
+    #[error("Line {line}: transitions have {expected} states, got {got}")]
+    TransitionLength {
+        line: usize,
+        expected: usize,
+        got: usize,
+    },
+
+    #[error("Line {line}: the new state '{name}' should be one of the states before it")]
+    UnboundOutput { line: usize, name: String },
+
+    #[error("Rule trees have 4 or 8 neighbours, got {got}")]
+    InvalidNeighbours { got: u64 },
+
+    #[error("Expected {expected} nodes, got {got}")]
+    NodeCount { expected: usize, got: usize },
+
+    #[error("Invalid node at index {index}")]
+    InvalidNode { index: usize },
+
+    #[error("The root node should be at level {expected}, got {got}")]
+    InvalidRoot { expected: u8, got: u8 },
+
+    #[error("Cells with no live neighbours must stay dead in rules of more than 2 states")]
+    NotQuiescent,
+}
+
+/// A transition function over any number of states, given as a decision tree like in Golly's
+/// `@TREE` sections.
+///
+/// The tree looks at the cells of a neighbourhood one at a time, in the order of
+/// [`MOORE_ORDER`], or [`VON_NEUMANN_ORDER`] for 4 neighbours. Each node has a child per state of
+/// the cell it looks at, and the nodes of the last level hold the new state of the cell.
+///
+/// See: https://golly.sourceforge.io/Help/formats.html#tree
+#[derive(Debug, Clone)]
+pub struct RuleTree {
+    name: String,
+
+    states: u16,
+
+    /// Number of neighbours, 4 or 8
+    neighbours: u8,
+
+    /// Every node as given to [`RuleTree::new`]
+    nodes: Vec<(u8, Vec<u32>)>,
+
+    /// Children of every node, one after the other. Children are given as the offset of their
+    /// first child, so that stepping through the tree is just indexing.
+    offsets: Vec<u32>,
+
+    /// Offset of the root
+    root: u32,
+}
+
+impl RuleTree {
+    /// Build a tree out of its nodes, given as their level and their child for each state, like
+    /// in `@TREE` sections. Children are the indices of nodes one level down, or new states for
+    /// nodes of level 1. The last node is the root, at one level per neighbour plus one.
+    pub fn new(
+        name: String,
+        states: u16,
+        neighbours: u8,
+        nodes: Vec<(u8, Vec<u32>)>,
+    ) -> Result<Self, RuleTableError> {
+        if !(2..=MAX_STATES).contains(&states) {
+            return Err(RuleTableError::InvalidStates {
+                line: 0,
+                got: states.to_string(),
+            });
+        }
+
+        if neighbours != 4 && neighbours != 8 {
+            return Err(RuleTableError::InvalidNeighbours {
+                got: neighbours as u64,
+            });
+        }
+
+        for (index, (level, children)) in nodes.iter().enumerate() {
+            let valid = children.len() == states as usize
+                && children.iter().all(|&child| match level {
+                    0 => false,
+                    1 => child < states as u32,
+                    // Children come first, so there can't be any cycle
+                    _ => nodes[..index]
+                        .get(child as usize)
+                        .is_some_and(|(l, _)| l + 1 == *level),
+                });
+
+            if !valid {
+                return Err(RuleTableError::InvalidNode { index });
+            }
+        }
+
+        let Some(&(level, _)) = nodes.last() else {
+            return Err(RuleTableError::NodeCount {
+                expected: 1,
+                got: 0,
+            });
+        };
+
+        if level != neighbours + 1 {
+            return Err(RuleTableError::InvalidRoot {
+                expected: neighbours + 1,
+                got: level,
+            });
+        }
+
+        let offsets = nodes
+            .iter()
+            .flat_map(|(level, children)| {
+                children.iter().map(move |&child| match level {
+                    1 => child,
+                    _ => child * states as u32,
+                })
+            })
+            .collect();
+
+        let tree = Self {
+            name,
+            states,
+            neighbours,
+            root: ((nodes.len() - 1) * states as usize) as u32,
+            nodes,
+            offsets,
+        };
+
+        // Like B0 rules, a finite world can't represent an infinity of cells coming to life. Unlike
+        // B0 rules, there's no complement to step instead.
+        if states > 2 && tree.next([0; 9]) != 0 {
+            return Err(RuleTableError::NotQuiescent);
+        }
+
+        Ok(tree)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    pub fn neighbours(&self) -> u8 {
+        self.neighbours
+    }
+
+    /// Every node, see [`RuleTree::new`]
+    pub fn nodes(&self) -> &[(u8, Vec<u32>)] {
+        &self.nodes
+    }
+
+    /// The state of the center cell of a 3x3 square of cells after an iteration. Cells are read
+    /// row by row from the top left.
+    pub fn next(&self, cells: [u8; 9]) -> u8 {
+        let order: &[usize] = if self.neighbours == 4 {
+            &VON_NEUMANN_ORDER
+        } else {
+            &MOORE_ORDER
+        };
+
+        order.iter().fold(self.root, |node, &i| {
+            self.offsets[node as usize + cells[i] as usize]
+        }) as u8
+    }
+}
+
+/// Load a rule from the Golly `.rule` file at `path`.
+///
+/// See [`read_rule_file`].
+pub fn load_rule_file<P: AsRef<Path>>(path: P) -> Result<RuleSet, RuleTableError> {
+    read_rule_file(&fs::read_to_string(path)?)
+}
+
+/// Parse a Golly `.rule` file, which describes a rule of any number of states with either a
+/// `@TABLE` or a `@TREE` section. If there are both, the tree is used. Other sections, like
+/// `@COLORS` and `@ICONS`, are ignored.
+///
+/// See: https://golly.sourceforge.io/Help/formats.html#rule
+pub fn read_rule_file(text: &str) -> Result<RuleSet, RuleTableError> {
+    let mut name = None;
+    let mut section = "";
+
+    // Lines of each section, along with their line number
+    let mut table = None;
+    let mut tree = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix('@') {
+            let (header, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+            match header {
+                "RULE" => name = Some(arg.trim().to_string()),
+                "TABLE" => table = Some(Vec::new()),
+                "TREE" => tree = Some(Vec::new()),
+                _ => {}
+            }
+
+            section = header;
+
+            continue;
+        }
+
+        let lines = match section {
+            "TABLE" => table.as_mut(),
+            "TREE" => tree.as_mut(),
+            _ => None,
+        };
+
+        if let Some(lines) = lines {
+            lines.push((i + 1, line));
+        }
+    }
+
+    let Some(name) = name else {
+        return Err(RuleTableError::NoRule);
+    };
+
+    let tree = match (tree, table) {
+        (Some(tree), _) => parse_tree(name, &tree)?,
+        (None, Some(table)) => parse_table(name, &table)?,
+        (None, None) => return Err(RuleTableError::NoTable),
+    };
+
+    Ok(RuleSet::from_tree(tree))
+}
+
+/// Parse the lines of a `@TREE` section
+fn parse_tree(name: String, lines: &[(usize, &str)]) -> Result<RuleTree, RuleTableError> {
+    let mut states = None;
+    let mut neighbours = None;
+    let mut len = None;
+    let mut nodes = Vec::new();
+
+    for &(line, s) in lines {
+        let invalid = || RuleTableError::InvalidLine {
+            line,
+            got: s.to_string(),
+        };
+
+        if let Some((key, value)) = s.split_once('=') {
+            let value: u64 = value.trim().parse().map_err(|_| invalid())?;
+
+            match key.trim() {
+                "num_states" => {
+                    states = Some(
+                        u16::try_from(value)
+                            .ok()
+                            .filter(|n| (2..=MAX_STATES).contains(n))
+                            .ok_or_else(|| RuleTableError::InvalidStates {
+                                line,
+                                got: value.to_string(),
+                            })?,
+                    )
+                }
+                "num_neighbors" => {
+                    neighbours = Some(match value {
+                        4 | 8 => value as u8,
+                        got => return Err(RuleTableError::InvalidNeighbours { got }),
+                    })
+                }
+                "num_nodes" => len = Some(value as usize),
+                _ => return Err(invalid()),
+            }
+
+            continue;
+        }
+
+        let mut numbers = s.split_whitespace().map(|n| n.parse::<u32>());
+
+        let level = match numbers.next() {
+            Some(Ok(level)) => u8::try_from(level).map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+
+        let children = numbers.collect::<Result<_, _>>().map_err(|_| invalid())?;
+
+        nodes.push((level, children));
+    }
+
+    let missing = |header| RuleTableError::MissingHeader {
+        line: lines.first().map_or(0, |&(line, _)| line),
+        header,
+    };
+
+    let states = states.ok_or_else(|| missing("num_states"))?;
+    let neighbours = neighbours.ok_or_else(|| missing("num_neighbors"))?;
+    let len = len.ok_or_else(|| missing("num_nodes"))?;
+
+    if nodes.len() != len {
+        return Err(RuleTableError::NodeCount {
+            expected: len,
+            got: nodes.len(),
+        });
+    }
+
+    RuleTree::new(name, states, neighbours, nodes)
+}
+
+/// A set of states, one bit each
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct States([u64; 4]);
+
+impl States {
+    fn all(states: u16) -> Self {
+        Self(std::array::from_fn(|i| {
+            let n = (states as usize).saturating_sub(64 * i).min(64);
+
+            if n == 64 { u64::MAX } else { (1 << n) - 1 }
+        }))
+    }
+
+    fn one(state: u8) -> Self {
+        Self::from_iter([state])
+    }
+
+    fn contains(&self, state: u8) -> bool {
+        self.0[state as usize / 64] & (1 << (state % 64)) != 0
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> {
+        (0..=u8::MAX).filter(|&state| self.contains(state))
+    }
+}
+
+impl FromIterator<u8> for States {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let mut set = Self([0; 4]);
+        for state in iter {
+            set.0[state as usize / 64] |= 1 << (state % 64);
+        }
+        set
+    }
+}
+
+/// A transition of a rule table, where the cells of a 3x3 square are numbered row by row from the
+/// top left
+struct Transition {
+    cells: [States; 9],
+
+    /// New state of the cell
+    output: u8,
+}
+
+/// A state or a variable of a rule table
+enum Token<'a> {
+    State(u8),
+    Variable(&'a str),
+}
+
+/// Parse the lines of a `@TABLE` section, and compile it to a tree.
+///
+/// Transitions are tried in order, and cells which match none of them stay the same. Like in
+/// Golly, a variable which shows up more than once in a transition takes the same value every
+/// time.
+fn parse_table<'a>(name: String, lines: &[(usize, &'a str)]) -> Result<RuleTree, RuleTableError> {
+    let mut states = None;
+    let mut clockwise: &[usize] = &MOORE_CLOCKWISE;
+    let mut symmetries = "none";
+    let mut variables: HashMap<&'a str, Vec<u8>> = HashMap::new();
+    let mut transitions = Vec::new();
+
+    for &(line, s) in lines {
+        if let Some((key, value)) = s.split_once(':') {
+            let value = value.trim();
+
+            match key.trim() {
+                "n_states" => {
+                    states = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|n| (2..=MAX_STATES).contains(n))
+                            .ok_or_else(|| RuleTableError::InvalidStates {
+                                line,
+                                got: value.to_string(),
+                            })?,
+                    )
+                }
+                "neighborhood" => {
+                    clockwise = match value {
+                        "Moore" => &MOORE_CLOCKWISE,
+                        "vonNeumann" => &VON_NEUMANN_CLOCKWISE,
+                        "hexagonal" => &HEXAGONAL_CLOCKWISE,
+                        _ => {
+                            return Err(RuleTableError::UnknownNeighbourhood {
+                                line,
+                                got: value.to_string(),
+                            });
+                        }
+                    }
+                }
+                "symmetries" => symmetries = value,
+                _ => {
+                    return Err(RuleTableError::InvalidLine {
+                        line,
+                        got: s.to_string(),
+                    });
+                }
+            }
+
+            continue;
+        }
+
+        let Some(states) = states else {
+            return Err(RuleTableError::MissingHeader {
+                line,
+                header: "n_states",
+            });
+        };
+
+        let token = |s: &'a str| -> Result<Token<'a>, RuleTableError> {
+            match s.parse::<u16>() {
+                Ok(state) if state < states => Ok(Token::State(state as u8)),
+                Ok(_) => Err(RuleTableError::InvalidState {
+                    line,
+                    got: s.to_string(),
+                }),
+                Err(_) if variables.contains_key(s) => Ok(Token::Variable(s)),
+                Err(_) => Err(RuleTableError::InvalidState {
+                    line,
+                    got: s.to_string(),
+                }),
+            }
+        };
+
+        if let Some(definition) = s.strip_prefix("var ") {
+            let invalid = || RuleTableError::InvalidVariable {
+                line,
+                got: s.to_string(),
+            };
+
+            let (var, values) = definition.split_once('=').ok_or_else(invalid)?;
+
+            // Braces can be left out around a single element
+            let values = values.trim();
+            let values = match values.strip_prefix('{') {
+                Some(values) => values.strip_suffix('}').ok_or_else(invalid)?,
+                None => values,
+            };
+
+            let mut set = Vec::new();
+            for value in values.split(',') {
+                match token(value.trim())? {
+                    Token::State(state) => set.push(state),
+                    Token::Variable(var) => set.extend(&variables[var]),
+                }
+            }
+
+            variables.insert(var.trim(), set);
+
+            continue;
+        }
+
+        // States are separated by commas or spaces, unless they are all single digits
+        let tokens: Vec<&str> = if s.contains([',', ' ', '\t']) {
+            s.split([',', ' ', '\t'])
+                .filter(|t| !t.is_empty())
+                .collect()
+        } else {
+            (0..s.len()).filter_map(|i| s.get(i..i + 1)).collect()
+        };
+
+        let expected = clockwise.len() + 2;
+        if tokens.len() != expected {
+            return Err(RuleTableError::TransitionLength {
+                line,
+                expected,
+                got: tokens.len(),
+            });
+        }
+
+        let tokens = tokens
+            .iter()
+            .map(|&t| token(t))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for transition in expand(&tokens, &variables, clockwise, states, line)? {
+            for cells in symmetric(&transition.cells, clockwise, symmetries, line)? {
+                transitions.push(Transition {
+                    cells,
+                    output: transition.output,
+                });
+            }
+        }
+    }
+
+    let Some(states) = states else {
+        return Err(RuleTableError::MissingHeader {
+            line: lines.first().map_or(0, |&(line, _)| line),
+            header: "n_states",
+        });
+    };
+
+    let neighbours = if clockwise.len() == 4 { 4 } else { 8 };
+
+    RuleTree::new(
+        name,
+        states,
+        neighbours,
+        compile(&transitions, states, neighbours),
+    )
+}
+
+/// Turn the tokens of a transition, the cell itself, its neighbours clockwise and its new state,
+/// into transitions without variables that show up more than once
+fn expand(
+    tokens: &[Token],
+    variables: &HashMap<&str, Vec<u8>>,
+    clockwise: &[usize],
+    states: u16,
+    line: usize,
+) -> Result<Vec<Transition>, RuleTableError> {
+    let (output, inputs) = tokens.split_last().unwrap();
+
+    // Variables which show up more than once are bound
+    let mut bound: Vec<&str> = Vec::new();
+    for (i, token) in inputs.iter().enumerate() {
+        if let Token::Variable(var) = token
+            && !bound.contains(var)
+            && inputs[i + 1..]
+                .iter()
+                .any(|t| matches!(t, Token::Variable(v) if v == var))
+        {
+            bound.push(var);
+        }
+    }
+
+    if let Token::Variable(var) = output {
+        let appears = inputs
+            .iter()
+            .any(|t| matches!(t, Token::Variable(v) if v == var));
+
+        if !appears {
+            return Err(RuleTableError::UnboundOutput {
+                line,
+                name: var.to_string(),
+            });
+        }
+
+        if !bound.contains(var) {
+            bound.push(var);
+        }
+    }
+
+    // Every combination of values of the bound variables
+    let mut bindings: Vec<Vec<u8>> = vec![Vec::new()];
+    for var in &bound {
+        bindings = bindings
+            .iter()
+            .flat_map(|binding| {
+                variables[var].iter().map(move |&value| {
+                    let mut binding = binding.clone();
+                    binding.push(value);
+                    binding
+                })
+            })
+            .collect();
+    }
+
+    let set = |token: &Token, binding: &[u8]| match token {
+        Token::State(state) => States::one(*state),
+        Token::Variable(var) => match bound.iter().position(|b| b == var) {
+            Some(i) => States::one(binding[i]),
+            None => variables[var].iter().copied().collect(),
+        },
+    };
+
+    let transitions = bindings
+        .iter()
+        .map(|binding| {
+            // Cells the rule doesn't look at can be anything
+            let mut cells = [States::all(states); 9];
+
+            cells[CENTER] = set(&inputs[0], binding);
+            for (&cell, token) in clockwise.iter().zip(&inputs[1..]) {
+                cells[cell] = set(token, binding);
+            }
+
+            let output = set(output, binding).iter().next().unwrap_or_default();
+
+            Transition { cells, output }
+        })
+        .collect();
+
+    Ok(transitions)
+}
+
+/// Every distinct arrangement of `cells` under `symmetries`, starting with `cells` itself
+fn symmetric(
+    cells: &[States; 9],
+    clockwise: &[usize],
+    symmetries: &str,
+    line: usize,
+) -> Result<Vec<[States; 9]>, RuleTableError> {
+    let n = clockwise.len();
+    let neighbours: Vec<States> = clockwise.iter().map(|&i| cells[i]).collect();
+
+    let arrange = |order: &[States]| {
+        let mut cells = *cells;
+        for (&i, &set) in clockwise.iter().zip(order) {
+            cells[i] = set;
+        }
+        cells
+    };
+
+    let arrangements: Vec<Vec<States>> = if symmetries == "permute" {
+        permutations(&neighbours)
+    } else {
+        let (rotations, reflect) = match symmetries {
+            "none" => (1, false),
+            "reflect_horizontal" | "reflect" => (1, true),
+            _ => {
+                let rotations = symmetries.strip_prefix("rotate").unwrap_or_default();
+                let (rotations, reflect) = match rotations.strip_suffix("reflect") {
+                    Some(rotations) => (rotations, true),
+                    None => (rotations, false),
+                };
+
+                match rotations.parse::<usize>() {
+                    Ok(rotations) if rotations > 0 && n.is_multiple_of(rotations) => {
+                        (rotations, reflect)
+                    }
+                    _ => {
+                        return Err(RuleTableError::UnknownSymmetries {
+                            line,
+                            got: symmetries.to_string(),
+                        });
+                    }
+                }
+            }
+        };
+
+        let step = n / rotations;
+        let mut arrangements = Vec::new();
+
+        for r in 0..rotations {
+            let rotated: Vec<States> = (0..n).map(|i| neighbours[(i + r * step) % n]).collect();
+
+            if reflect {
+                arrangements.push((0..n).map(|i| rotated[(n - i) % n]).collect());
+            }
+
+            arrangements.push(rotated);
+        }
+
+        arrangements
+    };
+
+    let mut seen = HashSet::new();
+
+    Ok(arrangements
+        .iter()
+        .map(|order| arrange(order))
+        .filter(|cells| seen.insert(*cells))
+        .collect())
+}
+
+/// Every distinct permutation of `sets`
+fn permutations(sets: &[States]) -> Vec<Vec<States>> {
+    if sets.is_empty() {
+        return vec![Vec::new()];
+    }
+
+    let mut seen = HashSet::new();
+    let mut all = Vec::new();
+
+    for (i, &first) in sets.iter().enumerate() {
+        if !seen.insert(first) {
+            continue;
+        }
+
+        let mut rest = sets.to_vec();
+        rest.remove(i);
+
+        for mut permutation in permutations(&rest) {
+            permutation.insert(0, first);
+            all.push(permutation);
+        }
+    }
+
+    all
+}
+
+/// Compile transitions into the nodes of a tree, see [`RuleTree::new`]. The first transition a
+/// neighbourhood matches gives its new state.
+fn compile(transitions: &[Transition], states: u16, neighbours: u8) -> Vec<(u8, Vec<u32>)> {
+    let order: &[usize] = if neighbours == 4 {
+        &VON_NEUMANN_ORDER
+    } else {
+        &MOORE_ORDER
+    };
+
+    let mut compiler = Compiler {
+        transitions,
+        order,
+        states,
+        nodes: Vec::new(),
+        ids: HashMap::new(),
+        memo: HashMap::new(),
+    };
+
+    compiler.node(0, (0..transitions.len() as u32).collect());
+
+    compiler.nodes
+}
+
+struct Compiler<'a> {
+    transitions: &'a [Transition],
+
+    /// Cells in the order the tree visits them
+    order: &'a [usize],
+
+    states: u16,
+
+    nodes: Vec<(u8, Vec<u32>)>,
+
+    /// Index of every node in `nodes`
+    ids: HashMap<(u8, Vec<u32>), u32>,
+
+    /// Node for the transitions left after the first cells of `order`
+    memo: HashMap<(usize, Vec<u32>), u32>,
+}
+
+impl Compiler<'_> {
+    /// The node that looks at cell `order[i]`, given the transitions which match the cells
+    /// before it
+    fn node(&mut self, i: usize, candidates: Vec<u32>) -> u32 {
+        if let Some(&node) = self.memo.get(&(i, candidates.clone())) {
+            return node;
+        }
+
+        let cell = self.order[i];
+        let last = i + 1 == self.order.len();
+
+        let children = (0..self.states)
+            .map(|state| {
+                let state = state as u8;
+                let mut matching = candidates
+                    .iter()
+                    .copied()
+                    .filter(|&t| self.transitions[t as usize].cells[cell].contains(state));
+
+                if last {
+                    // The cell itself comes last, so this is its new state
+                    matching
+                        .next()
+                        .map_or(state, |t| self.transitions[t as usize].output)
+                        as u32
+                } else {
+                    let matching = matching.collect();
+
+                    self.node(i + 1, matching)
+                }
+            })
+            .collect();
+
+        let level = (self.order.len() - i) as u8;
+        let node = (level, children);
+
+        let id = match self.ids.get(&node) {
+            Some(&id) => id,
+            None => {
+                let id = self.nodes.len() as u32;
+
+                self.ids.insert(node.clone(), id);
+                self.nodes.push(node);
+
+                id
+            }
+        };
+
+        self.memo.insert((i, candidates), id);
+
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuleTableError;
+    use crate::world::World;
+
+    const WIREWORLD: &str = "\
+@RULE WireWorld
+
+# 0: empty, 1: electron head, 2: electron tail, 3: conductor
+
+@TABLE
+n_states:4
+neighborhood:Moore
+symmetries:permute
+
+var a={0,1,2,3}
+var b={a}
+var c={a}
+var d={a}
+var e={a}
+var f={a}
+var g={a}
+var h={a}
+var i={0,2,3}
+var j={i}
+var k={i}
+var l={i}
+var m={i}
+var n={i}
+
+1,a,b,c,d,e,f,g,h,2
+2,a,b,c,d,e,f,g,h,3
+3,i,j,k,l,m,n,a,1,1
+3,i,j,k,l,m,1,n,1,1
+
+@COLORS
+1 0 128 255
+";
+
+    #[test]
+    fn test_wireworld() -> Result<(), RuleTableError> {
+        let rule = super::read_rule_file(WIREWORLD)?;
+        let tree = rule.tree().unwrap();
+
+        assert_eq!(rule.states(), 4);
+        assert_eq!(tree.name(), "WireWorld");
+
+        // Conductors turn into electron heads next to 1 or 2 of them, wherever they are
+        assert_eq!(tree.next([1, 0, 0, 0, 3, 0, 0, 0, 0]), 1);
+        assert_eq!(tree.next([0, 0, 3, 0, 3, 1, 1, 0, 2]), 1);
+        assert_eq!(tree.next([1, 0, 1, 0, 3, 0, 0, 0, 1]), 3);
+        assert_eq!(tree.next([0, 0, 0, 3, 3, 3, 0, 0, 0]), 3);
+        assert_eq!(tree.next([1, 1, 1, 1, 1, 1, 1, 1, 1]), 2);
+        assert_eq!(tree.next([0, 3, 0, 0, 2, 0, 0, 0, 0]), 3);
+        assert_eq!(tree.next([1, 1, 1, 1, 0, 1, 1, 1, 1]), 0);
+
+        // An electron travels along a wire
+        let mut world = World::new(rule);
+        world.grow(2);
+
+        for x in 0..16 {
+            world.set_state(x, 0, 3);
+        }
+        world.set_state(0, 0, 2);
+        world.set_state(1, 0, 1);
+
+        world.step(5);
+
+        let states: Vec<_> = (0..16).map(|x| world.state(x, 0)).collect();
+        assert_eq!(states, [3, 3, 3, 3, 3, 2, 1, 3, 3, 3, 3, 3, 3, 3, 3, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_tree() -> Result<(), RuleTableError> {
+        let rule = super::read_rule_file(WIREWORLD)?;
+        let tree = rule.tree().unwrap();
+
+        let mut text = format!(
+            "@RULE WireWorld\n@TREE\nnum_states=4\nnum_neighbors=8\nnum_nodes={}\n",
+            tree.nodes().len()
+        );
+
+        for (level, children) in tree.nodes() {
+            let children: Vec<_> = children.iter().map(|c| c.to_string()).collect();
+            text += &format!("{level} {}\n", children.join(" "));
+        }
+
+        let parsed = super::read_rule_file(&text)?;
+        assert_eq!(parsed.tree().unwrap().nodes(), tree.nodes());
+
+        // Trees of 2 states are stored like any other rule
+        let life = super::read_rule_file(
+            "@RULE Life
+            @TABLE
+            n_states:2
+            neighborhood:Moore
+            symmetries:permute
+            var a={0,1}
+            var b=a
+            var c=a
+            var d=a
+            var e=a
+            0,1,1,1,0,0,0,0,0,1
+            1,1,1,0,0,0,0,0,0,1
+            1,1,1,1,0,0,0,0,0,1
+            a,b,c,d,e,0,0,0,0,0
+            a,1,1,1,1,b,c,d,e,0",
+        )?;
+
+        insta::assert_debug_snapshot!(life, @r#"
+        RuleSet {
+            rule: "b3/s23",
+            ext: None,
+        }
+        "#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_table_errors() {
+        let err = super::read_rule_file("@RULE Test\n@TABLE\nn_states:3\n0,x,1,0,0,0,0,0,0,1\n");
+        assert!(matches!(
+            err,
+            Err(RuleTableError::InvalidState { line: 4, .. })
+        ));
+
+        let err = super::read_rule_file("@RULE Test\n@TABLE\nn_states:3\n0,1,1,0,0,0,0,0,0\n");
+        assert!(matches!(
+            err,
+            Err(RuleTableError::TransitionLength {
+                line: 4,
+                expected: 10,
+                got: 9
+            })
+        ));
+
+        // Cells can't come to life out of nothing
+        let rule = super::read_rule_file("@RULE Test\n@TABLE\nn_states:3\n0000000000");
+        assert!(rule.is_ok());
+        let err = super::read_rule_file("@RULE Test\n@TABLE\nn_states:3\n0000000002");
+        assert!(matches!(err, Err(RuleTableError::NotQuiescent)));
+
+        let err = super::read_rule_file("@TABLE\nn_states:3\n");
+        assert!(matches!(err, Err(RuleTableError::NoRule)));
+    }
+}
@@ -0,0 +1,707 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::cell::Cell;
+use crate::cell::CellBuf;
+use crate::cell::LEAF_MASK;
+use crate::checksum::crc32;
+use crate::rule_set::Edges;
+use crate::rule_set::Hensel;
+use crate::rule_set::MAX_STATES;
+use crate::rule_set::Neighbourhood;
+use crate::rule_set::RuleExtension;
+use crate::rule_set::RuleSet;
+use crate::rule_set::RuleTopology;
+use crate::rule_table::RuleTableError;
+use crate::rule_table::RuleTree;
+use crate::world::World;
+
+const MAGIC: [u8; 4] = *b"HLSN";
+
+/// Bumped whenever the layout changes in a way older readers can't handle
+pub const VERSION: u16 = 4;
+
+/// The snapshot carries memoized results
+const FLAG_RESULTS: u8 = 1;
+
+const RULE_OUTER_TOTALISTIC: u8 = 0;
+const RULE_ISOTROPIC: u8 = 1;
+const RULE_MAP: u8 = 2;
+const RULE_VON_NEUMANN: u8 = 3;
+const RULE_HEXAGONAL: u8 = 4;
+const RULE_TREE: u8 = 5;
+
+const NODE_LEAF: u8 = 0;
+const NODE_INNER: u8 = 1;
+
+/// Stands in for the number of iterations of full results, see [`CellBuf::result`]
+const FULL_RESULT: u8 = u8::MAX;
+
+/// The largest world we can address with a [`WorldOffset`](crate::WorldOffset)
+const MAX_DEPTH: u8 = 127;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Not a snapshot")]
+    BadMagic,
+
+    #[error("Unsupported snapshot version: {version}")]
+    UnsupportedVersion { version: u16 },
+
+    #[error("Checksum mismatch: expected {expected:#010x}, got {got:#010x}")]
+    ChecksumMismatch { expected: u32, got: u32 },
+
+    #[error("Unexpected EOF")]
+    UnexpectedEof,
+
+    #[error("Unknown rule kind: {kind}")]
+    UnknownRule { kind: u8 },
+
+    #[error("Unknown topology: {topology}")]
+    UnknownTopology { topology: u8 },
+
+    #[error("Unknown edges: {edges}")]
+    UnknownEdges { edges: u8 },
+
+    #[error("Invalid number of states: {states}")]
+    InvalidStates { states: u16 },
+
+    #[error("Invalid rule tree: {0}")]
+    RuleTree(#[from] RuleTableError),
+
+    #[error("Invalid varint")]
+    InvalidVarint,
+
+    #[error("Index {index} out of range, there are only {len} nodes before it")]
+    IndexOutOfRange { index: u64, len: usize },
+
+    #[error("Invalid node at index {index}")]
+    InvalidNode { index: usize },
+
+    #[error("Invalid root: a 2^{depth} world can't have its root at index {root}")]
+    InvalidRoot { root: usize, depth: u8 },
+
+    #[error("Invalid memoized result for the node at index {index}")]
+    InvalidResult { index: usize },
+
+    #[error("Trailing bytes after the snapshot")]
+    TrailingBytes,
+}
+
+/// Save `world` to a snapshot file at `path`.
+///
+/// See [`write_snapshot`].
+pub fn save_snapshot<P: AsRef<Path>>(world: &World, with_results: bool, path: P) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_snapshot(world, with_results, &mut file)?;
+
+    file.flush()
+}
+
+/// Load a world from the snapshot file at `path`.
+///
+/// See [`read_snapshot`].
+pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<World, SnapshotError> {
+    read_snapshot(File::open(path)?)
+}
+
+/// Write `world` as a binary snapshot.
+///
+/// The snapshot holds the rule, the generation counter and the cells reachable from the root, in
+/// a canonical order: children always come before their parents, visited `nw`, `ne`, `sw`, `se`.
+/// Two worlds with the same pattern, depth, rule and generation yield the same bytes. If
+/// `with_results` is set, the memoized results between those cells are saved as well, so that a
+/// restored world doesn't have to compute them all over again.
+///
+/// # Layout
+/// Numbers are little endian, and indices are LEB128 varints. Index 0 is always the void cell, so
+/// it isn't stored.
+/// ```notrust
+/// magic "HLSN", version: u16, flags: u8
+/// rule kind: u8
+///     outer totalistic: 0, births: u16, survivals: u16
+///     isotropic: 1, births: [u16; 9], survivals: [u16; 9]
+///     map: 2, transitions: [u8; 64], most significant bit first
+///     von neumann: 3, births: u16, survivals: u16
+///     hexagonal: 4, births: u16, survivals: u16
+///     tree: 5, name: varint length then bytes, states: u16, neighbours: u8, nodes: varint, then
+///         every node: level: u8, children: [varint; states]
+/// extension: u8 (0 or 1), [topology: u8, width: u32, height: u32, twist: u8, shift: u8, u32]
+///     edges of the twist and shift: none: 0, horizontal: 1, vertical: 2
+/// states: u16
+/// nodes: varint, then every node
+///     leaf:  0: u8, rules: [u16; 4], or with more than 2 states, blocks: [[u8; 16]; 4]
+///     inner: 1: u8, children: [varint; 4]
+/// root: varint, depth: u8, generation: u64
+/// [results: varint, then every result: node: varint, iterations: u8, phase: u8, result: varint]
+/// crc32 of everything above: u32
+/// ```
+pub fn write_snapshot<W: Write>(world: &World, with_results: bool, mut w: W) -> io::Result<()> {
+    let (buf, roots) = world.buf.compact(&[world.root]);
+
+    let mut out = Vec::new();
+
+    out.extend(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+    out.push(if with_results { FLAG_RESULTS } else { 0 });
+
+    write_rule(world.rule(), &mut out);
+
+    write_varint(buf.len() as u64 - 1, &mut out);
+    for cell in &buf[1..] {
+        match cell.rules() {
+            Some(_) if buf.states() > 2 => {
+                out.push(NODE_LEAF);
+                [cell.nw & !LEAF_MASK, cell.ne, cell.sw, cell.se]
+                    .iter()
+                    .for_each(|&b| out.extend(buf.block(b)));
+            }
+            Some(rules) => {
+                out.push(NODE_LEAF);
+                rules.iter().for_each(|r| out.extend(r.to_le_bytes()));
+            }
+            None => {
+                out.push(NODE_INNER);
+                [cell.nw, cell.ne, cell.sw, cell.se]
+                    .iter()
+                    .for_each(|&ptr| write_varint(ptr as u64, &mut out));
+            }
+        }
+    }
+
+    write_varint(roots[0] as u64, &mut out);
+    out.push(world.depth);
+    out.extend(world.generation().to_le_bytes());
+
+    if with_results {
+        // Results whose cell was dropped from the table are of no use to anyone
+        let mut results: Vec<_> = buf
+            .results()
+            .filter_map(|(cell, j, phase, res)| {
+                Some((buf.index_of(&cell)?, j.unwrap_or(FULL_RESULT), phase, res))
+            })
+            .collect();
+        results.sort_unstable();
+
+        write_varint(results.len() as u64, &mut out);
+        for (ptr, j, phase, res) in results {
+            write_varint(ptr as u64, &mut out);
+            out.push(j);
+            out.push(phase);
+            write_varint(res as u64, &mut out);
+        }
+    }
+
+    out.extend(crc32(&out).to_le_bytes());
+
+    w.write_all(&out)
+}
+
+/// Read a world back from a snapshot written by [`write_snapshot`].
+///
+/// The whole snapshot is checked before anything is built, so a corrupted snapshot is an error
+/// rather than a broken world. Loading takes time linear in the number of nodes.
+pub fn read_snapshot<R: Read>(mut r: R) -> Result<World, SnapshotError> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+
+    if !bytes.starts_with(&MAGIC) {
+        return Err(SnapshotError::BadMagic);
+    }
+
+    let Some((body, crc)) = bytes.split_last_chunk::<4>() else {
+        return Err(SnapshotError::UnexpectedEof);
+    };
+
+    let mut r = Reader { bytes: body };
+    r.take(MAGIC.len())?;
+
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(SnapshotError::UnsupportedVersion { version });
+    }
+
+    let (expected, got) = (u32::from_le_bytes(*crc), crc32(body));
+    if expected != got {
+        return Err(SnapshotError::ChecksumMismatch { expected, got });
+    }
+
+    let flags = r.u8()?;
+    let rule = read_rule(&mut r)?;
+
+    // Depth of every node, so that we can make sure the children of a node are all the same size.
+    // The void cell fits anywhere.
+    let n = r.varint()?;
+    let mut depths: Vec<Option<u8>> = vec![None];
+    let mut buf = CellBuf::with_states(rule.states());
+
+    for index in 1..=n {
+        let index = index as usize;
+
+        let (cell, depth) = match r.u8()? {
+            NODE_LEAF if rule.states() > 2 => {
+                let mut blocks = [0; 4];
+
+                for b in &mut blocks {
+                    let block: [u8; 16] = r.take(16)?.try_into().unwrap();
+
+                    if block.iter().any(|&state| state as u16 >= rule.states()) {
+                        return Err(SnapshotError::InvalidNode { index });
+                    }
+
+                    *b = buf.insert_block(block);
+                }
+
+                let [nw, ne, sw, se] = blocks;
+
+                (Cell::leaf(nw, ne, sw, se), 3)
+            }
+            NODE_LEAF => {
+                let [nw, ne, sw, se] = [r.u16()?, r.u16()?, r.u16()?, r.u16()?];
+
+                (
+                    Cell::leaf(nw as usize, ne as usize, sw as usize, se as usize),
+                    3,
+                )
+            }
+            NODE_INNER => {
+                let mut children = [0; 4];
+                let mut depth = None;
+
+                for child in &mut children {
+                    *child = r.index(index)?;
+
+                    match (depth, depths[*child]) {
+                        (_, None) => {}
+                        (None, d) => depth = d,
+                        (Some(a), Some(b)) if a == b => {}
+                        _ => return Err(SnapshotError::InvalidNode { index }),
+                    }
+                }
+
+                let [nw, ne, sw, se] = children;
+                let Some(depth) = depth.filter(|&d| d < MAX_DEPTH) else {
+                    return Err(SnapshotError::InvalidNode { index });
+                };
+
+                (Cell::new(nw, ne, sw, se), depth + 1)
+            }
+            _ => return Err(SnapshotError::InvalidNode { index }),
+        };
+
+        // Empty or duplicate cells would throw every index after them off
+        if buf.insert(cell) != index {
+            return Err(SnapshotError::InvalidNode { index });
+        }
+
+        depths.push(Some(depth));
+    }
+
+    let len = buf.len();
+
+    let root = r.varint()? as usize;
+    let depth = r.u8()?;
+    let generation = r.u64()?;
+
+    if root >= len || !(3..=MAX_DEPTH).contains(&depth) || depths[root].is_some_and(|d| d != depth)
+    {
+        return Err(SnapshotError::InvalidRoot { root, depth });
+    }
+
+    if flags & FLAG_RESULTS != 0 {
+        // Only B0 rules have a second phase
+        let phases = if rule.transitions()[0] { 2 } else { 1 };
+
+        for _ in 0..r.varint()? {
+            let index = r.index(len)?;
+            let j = r.u8()?;
+            let phase = r.u8()?;
+            let res = r.index(len)?;
+
+            let invalid = SnapshotError::InvalidResult { index };
+
+            // Results are the center half of a non-leaf cell
+            let Some(depth) = depths[index].filter(|&d| d > 3) else {
+                return Err(invalid);
+            };
+
+            if phase >= phases || depths[res].is_some_and(|d| d != depth - 1) {
+                return Err(invalid);
+            }
+
+            let j = match j {
+                FULL_RESULT => None,
+                j if j < depth - 3 => Some(j),
+                _ => return Err(invalid),
+            };
+
+            buf.set_result(buf[index], j, phase, res);
+        }
+    }
+
+    if !r.bytes.is_empty() {
+        return Err(SnapshotError::TrailingBytes);
+    }
+
+    Ok(World::from_parts(rule, buf, root, depth, generation))
+}
+
+fn write_rule(rule: &RuleSet, out: &mut Vec<u8>) {
+    match (rule.hensel(), rule.map()) {
+        _ if let Some(tree) = rule.tree() => {
+            out.push(RULE_TREE);
+            write_varint(tree.name().len() as u64, out);
+            out.extend(tree.name().as_bytes());
+            out.extend(tree.states().to_le_bytes());
+            out.push(tree.neighbours());
+
+            write_varint(tree.nodes().len() as u64, out);
+            for (level, children) in tree.nodes() {
+                out.push(*level);
+                for &child in children {
+                    write_varint(child as u64, out);
+                }
+            }
+        }
+        (Some(hensel), _) => {
+            out.push(RULE_ISOTROPIC);
+            for c in hensel.births.iter().chain(&hensel.survivals) {
+                out.extend(c.to_le_bytes());
+            }
+        }
+        (_, Some(transitions)) => {
+            out.push(RULE_MAP);
+            for byte in transitions.chunks(8) {
+                out.push(byte.iter().fold(0, |acc, &t| acc << 1 | t as u8));
+            }
+        }
+        (None, None) => {
+            out.push(match rule.neighbourhood() {
+                Neighbourhood::Moore => RULE_OUTER_TOTALISTIC,
+                Neighbourhood::VonNeumann => RULE_VON_NEUMANN,
+                Neighbourhood::Hexagonal => RULE_HEXAGONAL,
+            });
+            out.extend(rule.births().to_le_bytes());
+            out.extend(rule.survivals().to_le_bytes());
+        }
+    }
+
+    match rule.extension() {
+        None => out.push(0),
+        Some(ext) => {
+            out.push(1);
+            out.push(match ext.topology {
+                RuleTopology::Planar => 0,
+                RuleTopology::Torus => 1,
+                RuleTopology::KleinBottle => 2,
+                RuleTopology::Spherical => 3,
+                RuleTopology::CrossSurface => 4,
+            });
+            out.extend(ext.width.to_le_bytes());
+            out.extend(ext.height.to_le_bytes());
+
+            let edges = |edges| match edges {
+                None => 0,
+                Some(Edges::Horizontal) => 1,
+                Some(Edges::Vertical) => 2,
+            };
+            out.push(edges(ext.twist));
+            out.push(edges(ext.shift.map(|(edges, _)| edges)));
+            out.extend(ext.shift.map_or(0, |(_, n)| n).to_le_bytes());
+        }
+    }
+
+    out.extend(rule.states().to_le_bytes());
+}
+
+fn read_rule(r: &mut Reader) -> Result<RuleSet, SnapshotError> {
+    let rule = match r.u8()? {
+        RULE_OUTER_TOTALISTIC => RuleSet::new(r.u16()?, r.u16()?),
+        RULE_ISOTROPIC => {
+            let mut configs = [0; 18];
+            for c in &mut configs {
+                *c = r.u16()?;
+            }
+
+            let (births, survivals) = configs.split_at(9);
+
+            RuleSet::isotropic(Hensel {
+                births: births.try_into().unwrap(),
+                survivals: survivals.try_into().unwrap(),
+            })
+        }
+        RULE_MAP => {
+            let bytes = r.take(64)?;
+
+            RuleSet::from_transitions(std::array::from_fn(|i| {
+                bytes[i / 8] & (1 << (7 - i % 8)) != 0
+            }))
+        }
+        RULE_VON_NEUMANN => {
+            RuleSet::with_neighbourhood(r.u16()?, r.u16()?, Neighbourhood::VonNeumann)
+        }
+        RULE_HEXAGONAL => RuleSet::with_neighbourhood(r.u16()?, r.u16()?, Neighbourhood::Hexagonal),
+        RULE_TREE => {
+            let len = r.varint()? as usize;
+            let name = String::from_utf8_lossy(r.take(len)?).into_owned();
+            let states = r.u16()?;
+            let neighbours = r.u8()?;
+
+            let len = r.varint()?;
+            let mut nodes = Vec::new();
+            for _ in 0..len {
+                let level = r.u8()?;
+                let children = (0..states)
+                    .map(|_| Ok(r.varint()? as u32))
+                    .collect::<Result<_, SnapshotError>>()?;
+
+                nodes.push((level, children));
+            }
+
+            RuleSet::from_tree(RuleTree::new(name, states, neighbours, nodes)?)
+        }
+        kind => return Err(SnapshotError::UnknownRule { kind }),
+    };
+
+    let rule = if r.u8()? == 0 {
+        rule
+    } else {
+        let topology = match r.u8()? {
+            0 => RuleTopology::Planar,
+            1 => RuleTopology::Torus,
+            2 => RuleTopology::KleinBottle,
+            3 => RuleTopology::Spherical,
+            4 => RuleTopology::CrossSurface,
+            topology => return Err(SnapshotError::UnknownTopology { topology }),
+        };
+
+        let (width, height) = (r.u32()?, r.u32()?);
+
+        let mut edges = || match r.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(Edges::Horizontal)),
+            2 => Ok(Some(Edges::Vertical)),
+            edges => Err(SnapshotError::UnknownEdges { edges }),
+        };
+        let twist = edges()?;
+        let shift = edges()?;
+        let shift = shift.zip(Some(r.u32()?));
+
+        let ext = RuleExtension {
+            topology,
+            width,
+            height,
+            twist,
+            shift,
+        };
+
+        rule.extended(Some(ext))
+    };
+
+    let states = r.u16()?;
+
+    // Rule trees carry their own states
+    if states == rule.states() {
+        return Ok(rule);
+    }
+
+    // Generations rules can't have B0
+    if !(2..=MAX_STATES).contains(&states) || states > 2 && rule.transitions()[0] {
+        return Err(SnapshotError::InvalidStates { states });
+    }
+
+    Ok(rule.generations(states))
+}
+
+/// Write `n` as an unsigned LEB128 varint
+fn write_varint(mut n: u64, out: &mut Vec<u8>) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+
+    out.push(n as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        let Some((head, tail)) = self.bytes.split_at_checked(n) else {
+            return Err(SnapshotError::UnexpectedEof);
+        };
+
+        self.bytes = tail;
+
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    fn varint(&mut self) -> Result<u64, SnapshotError> {
+        let mut n = 0;
+
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            n |= ((b & 0x7F) as u64) << shift;
+
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+
+        Err(SnapshotError::InvalidVarint)
+    }
+
+    /// Read an index, which must point before `len`
+    fn index(&mut self, len: usize) -> Result<usize, SnapshotError> {
+        match self.varint()? {
+            i if i < len as u64 => Ok(i as usize),
+            index => Err(SnapshotError::IndexOutOfRange { index, len }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::WorldOffset;
+    use crate::census::world_with;
+    use crate::rule_set::B3S23;
+    use crate::rule_set::RuleSet;
+    use crate::rule_table::read_rule_file;
+    use crate::world::World;
+    use crate::world::glider;
+
+    use super::SnapshotError;
+
+    /// Cells in a state of 1 or more, along with it, sorted
+    fn cells(world: &World) -> Vec<(WorldOffset, WorldOffset, u8)> {
+        let mut cells: Vec<_> = world
+            .live_cells()
+            .map(|(x, y)| (x, y, world.state(x, y)))
+            .collect();
+        cells.sort();
+
+        cells
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() -> Result<(), SnapshotError> {
+        // Births only from a single neighbour to the north, on top of Life
+        let mut transitions = B3S23.transitions();
+        transitions[0b010_000_000] = true;
+        let map = RuleSet::from_transitions(transitions).to_map();
+
+        // Isolated cells in state 1 decay through state 2, with a rule tree of 3 states
+        let tree = read_rule_file(
+            "@RULE Decay\n@TABLE\nn_states:3\n1,0,0,0,0,0,0,0,0,2\n2,0,0,0,0,0,0,0,0,0\n",
+        )
+        .unwrap();
+
+        let rules = [
+            "B3/S23:T30+5,20",
+            "B2/S345/C4:K40*,30",
+            "B2-a/S12",
+            &format!("{map}:P40,30"),
+            "B1/S1V:S20",
+            "B2/S12H:C24,24",
+            "B0/S8",
+        ]
+        .map(|rule| rule.parse::<RuleSet>().unwrap());
+
+        let worlds = || {
+            let mut worlds = vec![glider()];
+
+            for rule in rules.iter().chain([&tree]) {
+                // The R-pentomino, and a cell in every other state
+                let mut pattern = vec![(0, 1, 1), (1, 1, 1), (-1, 0, 1), (0, 0, 1), (0, -1, 1)];
+                for state in 2..rule.states() {
+                    pattern.push((3 * state as WorldOffset, 5, state as u8));
+                }
+
+                worlds.push(world_with(rule, &pattern));
+            }
+
+            worlds
+        };
+
+        for (mut world, mut expected) in worlds().into_iter().zip(worlds()) {
+            world.step(100);
+            expected.step(121);
+
+            for with_results in [false, true] {
+                let mut bytes = Vec::new();
+                super::write_snapshot(&world, with_results, &mut bytes)?;
+
+                let mut restored = super::read_snapshot(bytes.as_slice())?;
+
+                assert_eq!(restored.rule().to_string(), world.rule().to_string());
+                assert_eq!(restored.generation(), world.generation());
+                assert_eq!(restored.depth, world.depth);
+                assert_eq!(cells(&restored), cells(&world));
+
+                // Canonical, so writing it back yields the same bytes
+                let mut again = Vec::new();
+                super::write_snapshot(&restored, with_results, &mut again)?;
+                assert_eq!(again, bytes);
+
+                // The restored world goes on just like the original one
+                restored.step(21);
+
+                assert_eq!(restored.generation(), 121);
+                assert_eq!(cells(&restored), cells(&expected));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_corrupted() -> Result<(), SnapshotError> {
+        let mut bytes = Vec::new();
+        super::write_snapshot(&glider(), false, &mut bytes)?;
+
+        bytes[12] ^= 1;
+
+        assert!(matches!(
+            super::read_snapshot(bytes.as_slice()),
+            Err(SnapshotError::ChecksumMismatch { .. })
+        ));
+
+        assert!(matches!(
+            super::read_snapshot(&b"GIF89a"[..]),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        Ok(())
+    }
+}
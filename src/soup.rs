@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::WorldOffset;
+use crate::census;
+use crate::checksum::sha256;
+use crate::rule_set::RuleSet;
+use crate::world::World;
+
+/// The longest period a soup is checked for once it is done evolving
+const CHECK_PERIOD: u64 = 1 << 7;
+
+#[derive(Debug, Error)]
+pub enum SoupError {
+    #[error("Unknown symmetry: '{got}'")]
+    UnknownSymmetry { got: String },
+}
+
+/// Which cells the center of symmetry lies on, in the suffix of the name of a [`Symmetry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Center {
+    /// The center of a cell, `1`
+    Cell,
+    /// The middle of the edge between two cells, `2`
+    Edge,
+    /// The corner between four cells, `4`
+    Vertex,
+}
+
+/// The symmetries of soups, named like in apgsearch. Besides [`Symmetry::C1`], the soup is
+/// mirrored or rotated into a bigger one, around the bottom right corner of the random part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+    /// No symmetry, `C1`
+    C1,
+    /// Rotations by 180°, like `C2_4`
+    C2(Center),
+    /// Rotations by 90°, `C4_1` and `C4_4`
+    C4(Center),
+    /// A reflection along a row, `D2_+1`, or between two rows, `D2_+2`
+    D2Plus(Center),
+    /// A reflection along a diagonal, `D2_x`
+    D2Cross,
+    /// Reflections along a row and a column, like `D4_+2`
+    D4Plus(Center),
+    /// Reflections along both diagonals, `D4_x1` and `D4_x4`
+    D4Cross(Center),
+    /// Every rotation and reflection, `D8_1` and `D8_4`
+    D8(Center),
+}
+
+impl Symmetry {
+    /// The center of symmetry, in half cells from the top left corner of a `w` by `h` soup
+    fn center(&self, w: i64, h: i64) -> (i64, i64) {
+        let center = match self {
+            Self::C1 | Self::D2Cross => Center::Vertex,
+            Self::C2(c) | Self::C4(c) | Self::D2Plus(c) => *c,
+            Self::D4Plus(c) | Self::D4Cross(c) | Self::D8(c) => *c,
+        };
+
+        match center {
+            Center::Cell => (2 * w - 1, 2 * h - 1),
+            Center::Edge => (2 * w - 1, 2 * h),
+            Center::Vertex => (2 * w, 2 * h),
+        }
+    }
+
+    /// Every image of the offset (`u`, `v`) from the center of symmetry, including itself
+    fn images(&self, u: i64, v: i64) -> Vec<(i64, i64)> {
+        let rotations = [(u, v), (-v, u), (-u, -v), (v, -u)];
+        let reflections = [(u, -v), (-u, v), (v, u), (-v, -u)];
+
+        match self {
+            Self::C1 => vec![(u, v)],
+            Self::C2(_) => vec![(u, v), (-u, -v)],
+            Self::C4(_) => rotations.to_vec(),
+            Self::D2Plus(_) => vec![(u, v), (u, -v)],
+            Self::D2Cross => vec![(u, v), (v, u)],
+            Self::D4Plus(_) => vec![(u, v), (-u, -v), (u, -v), (-u, v)],
+            Self::D4Cross(_) => vec![(u, v), (-u, -v), (v, u), (-v, -u)],
+            Self::D8(_) => [rotations, reflections].concat(),
+        }
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = SoupError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let center = |c: &str| match c {
+            "1" => Some(Center::Cell),
+            "2" => Some(Center::Edge),
+            "4" => Some(Center::Vertex),
+            _ => None,
+        };
+
+        let symmetry = match s.split_once('_') {
+            None if s == "C1" => Some(Self::C1),
+            Some(("D2", "x")) => Some(Self::D2Cross),
+            Some(("C2", c)) => center(c).map(Self::C2),
+            Some(("C4", c @ ("1" | "4"))) => center(c).map(Self::C4),
+            Some(("D2", c @ ("+1" | "+2"))) => center(&c[1..]).map(Self::D2Plus),
+            Some(("D4", c)) if c.starts_with('+') => center(&c[1..]).map(Self::D4Plus),
+            Some(("D4", c @ ("x1" | "x4"))) => center(&c[1..]).map(Self::D4Cross),
+            Some(("D8", c @ ("1" | "4"))) => center(c).map(Self::D8),
+            _ => None,
+        };
+
+        symmetry.ok_or_else(|| SoupError::UnknownSymmetry { got: s.to_string() })
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = |c: &Center| match c {
+            Center::Cell => 1,
+            Center::Edge => 2,
+            Center::Vertex => 4,
+        };
+
+        match self {
+            Self::C1 => write!(f, "C1"),
+            Self::C2(c) => write!(f, "C2_{}", n(c)),
+            Self::C4(c) => write!(f, "C4_{}", n(c)),
+            Self::D2Plus(c) => write!(f, "D2_+{}", n(c)),
+            Self::D2Cross => write!(f, "D2_x"),
+            Self::D4Plus(c) => write!(f, "D4_+{}", n(c)),
+            Self::D4Cross(c) => write!(f, "D4_x{}", n(c)),
+            Self::D8(c) => write!(f, "D8_{}", n(c)),
+        }
+    }
+}
+
+/// How soups are made and run
+#[derive(Debug, Clone)]
+pub struct SoupSettings {
+    /// Size of the random part of the soup
+    pub width: u32,
+    pub height: u32,
+
+    /// Chance of each cell of the random part to be alive
+    pub density: f64,
+
+    pub symmetry: Symmetry,
+
+    /// Soups that are still evolving after this many generations are given up on
+    pub max_generations: u64,
+}
+
+/// 16 by 16 soups without symmetry, like apgsearch
+impl Default for SoupSettings {
+    fn default() -> Self {
+        Self {
+            width: 16,
+            height: 16,
+            density: 0.5,
+            symmetry: Symmetry::C1,
+            max_generations: 1 << 15,
+        }
+    }
+}
+
+/// The outcome of running a soup, see [`run_soup`]
+#[derive(Debug, Clone)]
+pub struct SoupResult {
+    pub seed: String,
+
+    /// Number of generations the soup ran for, until it became periodic if it did
+    pub generations: u64,
+
+    /// Whether the soup became periodic within [`SoupSettings::max_generations`]
+    pub stable: bool,
+
+    /// See [`census::census`]
+    pub census: BTreeMap<String, usize>,
+}
+
+/// Random bits drawn from `seed`, by hashing it with SHA-256 then hashing every hash again
+struct SeedBits {
+    hash: [u8; 32],
+    bit: usize,
+}
+
+impl SeedBits {
+    fn new(seed: &str) -> Self {
+        Self {
+            hash: sha256(seed.as_bytes()),
+            bit: 0,
+        }
+    }
+
+    /// The next bit, most significant bit of each byte first
+    fn next_bit(&mut self) -> bool {
+        if self.bit == 256 {
+            self.hash = sha256(&self.hash);
+            self.bit = 0;
+        }
+
+        let bit = self.hash[self.bit / 8] & (0x80 >> (self.bit % 8)) != 0;
+        self.bit += 1;
+
+        bit
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        (0..32).fold(0, |n, _| (n << 1) | self.next_bit() as u32)
+    }
+}
+
+/// The live cells of the soup grown from `seed`.
+///
+/// The random part is filled row by row from the top left, with bits from the SHA-256 hash of
+/// the seed, which is hashed again for more bits. At a density of one half, every cell takes a
+/// single bit, and otherwise 32 bits. The soup is then completed by its [`Symmetry`], and lies
+/// around the origin.
+pub fn soup_cells(seed: &str, settings: &SoupSettings) -> Vec<(WorldOffset, WorldOffset)> {
+    assert!(
+        (0.0..=1.0).contains(&settings.density),
+        "The density should be between 0 and 1, got {}",
+        settings.density
+    );
+
+    let (w, h) = (settings.width as i64, settings.height as i64);
+    let (cx, cy) = settings.symmetry.center(w, h);
+
+    let mut bits = SeedBits::new(seed);
+    let mut alive = || {
+        if settings.density == 0.5 {
+            bits.next_bit()
+        } else {
+            (bits.next_u32() as f64) < settings.density * (1u64 << 32) as f64
+        }
+    };
+
+    let mut cells = Vec::new();
+
+    for y in 0..h {
+        for x in 0..w {
+            if !alive() {
+                continue;
+            }
+
+            for (u, v) in settings.symmetry.images(2 * x + 1 - cx, 2 * y + 1 - cy) {
+                let (x, y) = ((u + cx - 1) / 2, (v + cy - 1) / 2);
+
+                cells.push((x as WorldOffset - w as WorldOffset, (h - y) as WorldOffset));
+            }
+        }
+    }
+
+    cells.sort();
+    cells.dedup();
+
+    cells
+}
+
+/// A new world of `rule` holding the soup grown from `seed`, see [`soup_cells`]
+pub fn seed_world(rule: RuleSet, seed: &str, settings: &SoupSettings) -> World {
+    let cells: Vec<_> = soup_cells(seed, settings)
+        .into_iter()
+        .map(|(x, y)| (x, y, 1))
+        .collect();
+
+    census::world_with(&rule, &cells)
+}
+
+/// Run the soup grown from `seed` until it becomes periodic, and take its census.
+///
+/// The soup is checked for a period of up to [`CHECK_PERIOD`] generations with
+/// [`World::run_until_stable`], which leaves out the gliders it sends off. Soups whose other
+/// spaceships part ways never become periodic as a whole, and run for
+/// [`SoupSettings::max_generations`].
+pub fn run_soup(rule: &RuleSet, seed: &str, settings: &SoupSettings) -> SoupResult {
+    let mut world = seed_world(rule.clone(), seed, settings);
+
+    let stabilisation = world.run_until_stable(settings.max_generations, CHECK_PERIOD, false);
+
+    SoupResult {
+        seed: seed.to_string(),
+        generations: stabilisation.map_or(world.generation(), |s| s.generation),
+        stable: stabilisation.is_some(),
+        census: census::census(&world),
+    }
+}
+
+/// Run `count` soups, seeded with `prefix` followed by their index like apgsearch, and add up
+/// their censuses
+pub fn search(
+    rule: &RuleSet,
+    prefix: &str,
+    count: u64,
+    settings: &SoupSettings,
+) -> BTreeMap<String, usize> {
+    let mut total = BTreeMap::new();
+
+    for i in 0..count {
+        let result = run_soup(rule, &format!("{prefix}{i}"), settings);
+
+        for (code, n) in result.census {
+            *total.entry(code).or_insert(0) += n;
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rule_set::B3S23;
+
+    use super::Center;
+    use super::SoupSettings;
+    use super::Symmetry;
+
+    #[test]
+    fn test_symmetry_names() {
+        let names = [
+            "C1", "C2_1", "C2_2", "C2_4", "C4_1", "C4_4", "D2_+1", "D2_+2", "D2_x", "D4_+1",
+            "D4_+2", "D4_+4", "D4_x1", "D4_x4", "D8_1", "D8_4",
+        ];
+
+        for name in names {
+            assert_eq!(name.parse::<Symmetry>().unwrap().to_string(), name);
+        }
+
+        assert_eq!(
+            "C4_4".parse::<Symmetry>().unwrap(),
+            Symmetry::C4(Center::Vertex)
+        );
+
+        for name in ["C3", "C4_2", "D2_+4", "D2_x1", "D8_2", "C1_1"] {
+            assert!(name.parse::<Symmetry>().is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_soup_symmetries() {
+        let settings = |symmetry: &str| SoupSettings {
+            width: 4,
+            height: 4,
+            symmetry: symmetry.parse().unwrap(),
+            ..Default::default()
+        };
+
+        let c1 = super::soup_cells("k_test0", &settings("C1"));
+        assert!(
+            c1.iter()
+                .all(|&(x, y)| (-4..0).contains(&x) && (1..=4).contains(&y))
+        );
+
+        // Every cell has its images in the soup
+        let c4 = super::soup_cells("k_test0", &settings("C4_4"));
+        for &(x, y) in &c4 {
+            assert!(c4.contains(&(-y, x + 1)));
+        }
+
+        let d2 = super::soup_cells("k_test0", &settings("D2_+1"));
+        for &(x, y) in &d2 {
+            assert!(d2.contains(&(x, 2 - y)));
+        }
+
+        assert!(c4.len() >= c1.len() && d2.len() >= c1.len());
+    }
+
+    #[test]
+    fn test_run_soup() {
+        let settings = SoupSettings {
+            max_generations: 1 << 12,
+            ..Default::default()
+        };
+
+        let result = super::run_soup(&B3S23, "k_test0", &settings);
+
+        insta::assert_debug_snapshot!(result, @r#"
+        SoupResult {
+            seed: "k_test0",
+            generations: 335,
+            stable: true,
+            census: {
+                "xp2_7": 7,
+                "xs4_33": 1,
+                "xs6_696": 1,
+            },
+        }
+        "#);
+    }
+}
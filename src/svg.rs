@@ -0,0 +1,241 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use crate::WorldOffset;
+use crate::world::Rect;
+use crate::world::World;
+
+/// Room left around the image for coordinate labels, in SVG user units
+const LABEL_MARGIN: u64 = 40;
+
+const LABEL_FONT_SIZE: u64 = 10;
+
+/// How to draw a world with [`write_svg`]
+pub struct SvgOptions {
+    /// Sidelength of a cell, in SVG user units
+    pub cell_size: u64,
+
+    /// Draw lines between cells
+    pub grid: bool,
+
+    /// Outline the smallest region containing every live cell
+    pub bounding_box: bool,
+
+    /// Label the coordinates of every `n`th column and row
+    pub labels: Option<u64>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 10,
+            grid: false,
+            bounding_box: false,
+            labels: None,
+        }
+    }
+}
+
+/// Draw the region `rect` of `world` to an SVG file at `path`.
+///
+/// `rect` follows the same conventions as the [`Camera`], so [`Camera::viewport`] can be used to
+/// export whatever is on screen.
+///
+/// [`Camera`]: crate::camera::Camera
+/// [`Camera::viewport`]: crate::camera::Camera::viewport
+pub fn export_svg<P: AsRef<Path>>(
+    world: &World,
+    rect: Rect,
+    options: &SvgOptions,
+    path: P,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_svg(world, rect, options, &mut file)?;
+
+    file.flush()
+}
+
+/// Draw the region `rect` of `world` as an SVG image.
+///
+/// Live cells are drawn as black squares on a white background, where horizontal runs of live
+/// cells are merged into a single rectangle.
+pub fn write_svg<W: Write>(
+    world: &World,
+    rect: Rect,
+    options: &SvgOptions,
+    mut w: W,
+) -> io::Result<()> {
+    let s = options.cell_size;
+    let (width, height) = (rect.w * s, rect.h * s);
+
+    let m = if options.labels.is_some() {
+        LABEL_MARGIN
+    } else {
+        0
+    };
+
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="-{m} -{m} {} {}">"#,
+        width + m,
+        height + m,
+        width + m,
+        height + m,
+    )?;
+
+    writeln!(
+        w,
+        r#"<rect width="{width}" height="{height}" fill="white"/>"#
+    )?;
+
+    // Offsets of the live cells from the top left of `rect`, by row
+    let mut cells: Vec<(u64, u64)> = world
+        .live_cells_in(rect)
+        .map(|(x, y)| ((rect.y - y) as u64, (x - rect.x) as u64))
+        .collect();
+    cells.sort_unstable();
+
+    writeln!(w, r#"<g fill="black">"#)?;
+    for (row, col, len) in runs(&cells) {
+        writeln!(
+            w,
+            r#"<rect x="{}" y="{}" width="{}" height="{s}"/>"#,
+            col * s,
+            row * s,
+            len * s
+        )?;
+    }
+    writeln!(w, "</g>")?;
+
+    if options.grid {
+        let mut d = String::new();
+
+        for col in 0..=rect.w {
+            d.push_str(&format!("M{} 0V{height}", col * s));
+        }
+
+        for row in 0..=rect.h {
+            d.push_str(&format!("M0 {}H{width}", row * s));
+        }
+
+        writeln!(
+            w,
+            r##"<path d="{d}" stroke="#ccc" stroke-width="{}"/>"##,
+            s as f64 / 20.0
+        )?;
+    }
+
+    if options.bounding_box
+        && let Some(bbox) = world.bounding_box()
+    {
+        let (col, row) = (bbox.x - rect.x, rect.y - bbox.y);
+
+        writeln!(
+            w,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="red" stroke-width="{}"/>"#,
+            col * s as WorldOffset,
+            row * s as WorldOffset,
+            bbox.w * s,
+            bbox.h * s,
+            s as f64 / 10.0
+        )?;
+    }
+
+    if let Some(n) = options.labels {
+        let n = n.max(1) as WorldOffset;
+
+        writeln!(
+            w,
+            r#"<g font-family="monospace" font-size="{LABEL_FONT_SIZE}">"#
+        )?;
+
+        // Label columns along the top, centered on their cell
+        for col in 0..rect.w {
+            let x = rect.x + col as WorldOffset;
+
+            if x.rem_euclid(n) == 0 {
+                writeln!(
+                    w,
+                    r#"<text x="{}" y="-4" text-anchor="middle">{x}</text>"#,
+                    col * s + s / 2
+                )?;
+            }
+        }
+
+        // Label rows along the left, centered on their cell
+        for row in 0..rect.h {
+            let y = rect.y - row as WorldOffset;
+
+            if y.rem_euclid(n) == 0 {
+                writeln!(
+                    w,
+                    r#"<text x="-4" y="{}" text-anchor="end" dominant-baseline="middle">{y}</text>"#,
+                    row * s + s / 2
+                )?;
+            }
+        }
+
+        writeln!(w, "</g>")?;
+    }
+
+    writeln!(w, "</svg>")
+}
+
+/// Merge sorted `(row, column)` pairs into horizontal runs, given as `(row, column, length)`
+fn runs(cells: &[(u64, u64)]) -> Vec<(u64, u64, u64)> {
+    let mut runs: Vec<(u64, u64, u64)> = Vec::new();
+
+    for &(row, col) in cells {
+        match runs.last_mut() {
+            Some((r, c, len)) if *r == row && *c + *len == col => *len += 1,
+            _ => runs.push((row, col, 1)),
+        }
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::Rect;
+    use crate::world::glider;
+
+    use super::SvgOptions;
+
+    #[test]
+    fn test_write_svg() -> std::io::Result<()> {
+        let world = glider();
+
+        let options = SvgOptions {
+            bounding_box: true,
+            labels: Some(2),
+            ..Default::default()
+        };
+
+        let mut svg = Vec::new();
+        super::write_svg(&world, Rect::new(-1, 3, 4, 4), &options, &mut svg)?;
+
+        insta::assert_snapshot!(String::from_utf8_lossy(&svg), @r#"
+        <svg xmlns="http://www.w3.org/2000/svg" width="80" height="80" viewBox="-40 -40 80 80">
+        <rect width="40" height="40" fill="white"/>
+        <g fill="black">
+        <rect x="20" y="10" width="10" height="10"/>
+        <rect x="30" y="20" width="10" height="10"/>
+        <rect x="10" y="30" width="30" height="10"/>
+        </g>
+        <rect x="10" y="10" width="30" height="30" fill="none" stroke="red" stroke-width="1"/>
+        <g font-family="monospace" font-size="10">
+        <text x="15" y="-4" text-anchor="middle">0</text>
+        <text x="35" y="-4" text-anchor="middle">2</text>
+        <text x="-4" y="15" text-anchor="end" dominant-baseline="middle">2</text>
+        <text x="-4" y="35" text-anchor="end" dominant-baseline="middle">0</text>
+        </g>
+        </svg>
+        "#);
+
+        Ok(())
+    }
+}
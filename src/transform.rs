@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use crate::WorldOffset;
+use crate::cell::Cell;
+use crate::cell::CellBuf;
+use crate::cell::LEAF_MASK;
+
+/// One of the 8 symmetries of the square, about the corner shared by the 4 cells around the
+/// origin. Rotations are counterclockwise, with `y` increasing to the north.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    #[default]
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+
+    /// Mirror east and west
+    FlipX,
+
+    /// Mirror north and south
+    FlipY,
+
+    /// Mirror across the diagonal going to the north east
+    Transpose,
+
+    /// Mirror across the diagonal going to the north west
+    AntiTranspose,
+}
+
+impl Orientation {
+    pub const ALL: [Self; 8] = [
+        Self::Identity,
+        Self::Rotate90,
+        Self::Rotate180,
+        Self::Rotate270,
+        Self::FlipX,
+        Self::FlipY,
+        Self::Transpose,
+        Self::AntiTranspose,
+    ];
+
+    /// Where the cell at (`x`, `y`) ends up
+    pub fn apply(self, x: WorldOffset, y: WorldOffset) -> (WorldOffset, WorldOffset) {
+        match self {
+            Self::Identity => (x, y),
+            Self::Rotate90 => (-1 - y, x),
+            Self::Rotate180 => (-1 - x, -1 - y),
+            Self::Rotate270 => (y, -1 - x),
+            Self::FlipX => (-1 - x, y),
+            Self::FlipY => (x, -1 - y),
+            Self::Transpose => (y, x),
+            Self::AntiTranspose => (-1 - y, -1 - x),
+        }
+    }
+
+    /// The orientation undoing this one
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Rotate90 => Self::Rotate270,
+            Self::Rotate270 => Self::Rotate90,
+            orientation => orientation,
+        }
+    }
+
+    /// Where each of `n * n` squares, row by row from the top left, ends up, for `n` even
+    fn permutation<const N: usize>(self, n: WorldOffset) -> [usize; N] {
+        let half = n / 2;
+
+        std::array::from_fn(|i| {
+            let (col, row) = (i as WorldOffset % n, i as WorldOffset / n);
+            let (x, y) = self.apply(col - half, half - 1 - row);
+
+            ((half - 1 - y) * n + x + half) as usize
+        })
+    }
+}
+
+/// An [`Orientation`], followed by a translation `dx` cells to the east and `dy` cells to the
+/// north
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Transform {
+    pub orientation: Orientation,
+    pub dx: WorldOffset,
+    pub dy: WorldOffset,
+}
+
+impl Transform {
+    pub const fn new(orientation: Orientation, dx: WorldOffset, dy: WorldOffset) -> Self {
+        Self {
+            orientation,
+            dx,
+            dy,
+        }
+    }
+
+    /// Where the cell at (`x`, `y`) ends up
+    pub fn apply(&self, x: WorldOffset, y: WorldOffset) -> (WorldOffset, WorldOffset) {
+        let (x, y) = self.orientation.apply(x, y);
+
+        (x + self.dx, y + self.dy)
+    }
+}
+
+/// Orient the cell at `ptr` about its center and return the index of the result. Quadrants are
+/// swapped around recursively, down to the 4x4 blocks of leaves whose cells are permuted.
+///
+/// `memo` holds the cells oriented so far, for this orientation and this buffer.
+pub(crate) fn orient(
+    buf: &mut CellBuf,
+    ptr: usize,
+    orientation: Orientation,
+    memo: &mut HashMap<usize, usize>,
+) -> usize {
+    if ptr == 0 {
+        return 0;
+    }
+
+    if let Some(&n) = memo.get(&ptr) {
+        return n;
+    }
+
+    let cell = buf[ptr];
+    let quadrants: [usize; 4] = orientation.permutation(2);
+
+    let mut oriented = [0; 4];
+    let n = match cell.children() {
+        Some(children) => {
+            for (i, child) in children.into_iter().enumerate() {
+                oriented[quadrants[i]] = orient(buf, child, orientation, memo);
+            }
+
+            let [nw, ne, sw, se] = oriented;
+            buf.insert(Cell::new(nw, ne, sw, se))
+        }
+        None => {
+            let cells: [usize; 16] = orientation.permutation(4);
+            let blocks = [cell.nw & !LEAF_MASK, cell.ne, cell.sw, cell.se];
+
+            for (i, block) in blocks.into_iter().enumerate() {
+                let states = buf.block(block);
+
+                let mut block = [0; 16];
+                for (j, state) in states.into_iter().enumerate() {
+                    block[cells[j]] = state;
+                }
+
+                oriented[quadrants[i]] = buf.insert_block(block);
+            }
+
+            let [nw, ne, sw, se] = oriented;
+            buf.insert(Cell::leaf(nw, ne, sw, se))
+        }
+    };
+
+    memo.insert(ptr, n);
+
+    n
+}
+
+/// The `2^depth` cell lying `ox` cells to the east and `oy` cells to the south of the top left
+/// corner of the square made of `quadrants`, in `nw`, `ne`, `sw`, `se` order, which are
+/// `2^depth` cells each. Returns the index of the result.
+///
+/// Cells whose top left corner lines up with a quadrant are reused as is. `memo` holds the
+/// cells found so far, for these offsets and this buffer, since `ox` and `oy` are only ever
+/// taken modulo `2^depth` for a given depth.
+pub(crate) fn window(
+    buf: &mut CellBuf,
+    quadrants: [usize; 4],
+    depth: u8,
+    ox: WorldOffset,
+    oy: WorldOffset,
+    memo: &mut HashMap<([usize; 4], u8), usize>,
+) -> usize {
+    let size = 1 << depth;
+    let (ox, oy) = (ox.rem_euclid(size), oy.rem_euclid(size));
+
+    if quadrants == [0; 4] {
+        return 0;
+    }
+
+    if (ox, oy) == (0, 0) {
+        return quadrants[0];
+    }
+
+    if let Some(&n) = memo.get(&(quadrants, depth)) {
+        return n;
+    }
+
+    let n = if depth == 3 {
+        let states = quadrants.map(|ptr| buf.leaf_states(buf[ptr]));
+
+        buf.insert_leaf(std::array::from_fn(|row| {
+            std::array::from_fn(|col| {
+                let (col, row) = (ox as usize + col, oy as usize + row);
+
+                states[row / 8 * 2 + col / 8][row % 8][col % 8]
+            })
+        }))
+    } else {
+        // The 4x4 grid of the children of the quadrants, row by row from the top left
+        let children = quadrants.map(|ptr| buf[ptr].children().unwrap_or_default());
+        let grid: [usize; 16] = std::array::from_fn(|i| {
+            let (col, row) = (i % 4, i / 4);
+
+            children[row / 2 * 2 + col / 2][row % 2 * 2 + col % 2]
+        });
+
+        let half = size / 2;
+        let [nw, ne, sw, se] = [(0, 0), (half, 0), (0, half), (half, half)].map(|(qx, qy)| {
+            let (col, row) = (((ox + qx) / half) as usize, ((oy + qy) / half) as usize);
+            let quadrants = [
+                grid[row * 4 + col],
+                grid[row * 4 + col + 1],
+                grid[(row + 1) * 4 + col],
+                grid[(row + 1) * 4 + col + 1],
+            ];
+
+            window(buf, quadrants, depth - 1, ox + qx, oy + qy, memo)
+        });
+
+        buf.insert(Cell::new(nw, ne, sw, se))
+    };
+
+    memo.insert((quadrants, depth), n);
+
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Orientation;
+
+    #[test]
+    fn test_orientations() {
+        for orientation in Orientation::ALL {
+            let (x, y) = orientation.apply(3, -5);
+
+            assert_eq!(orientation.inverse().apply(x, y), (3, -5));
+        }
+
+        // The 4 cells around the origin are swapped around among themselves
+        assert_eq!(Orientation::Rotate90.apply(0, 0), (-1, 0));
+        assert_eq!(Orientation::Rotate90.apply(-1, 0), (-1, -1));
+        assert_eq!(Orientation::Transpose.apply(-1, 0), (0, -1));
+        assert_eq!(Orientation::AntiTranspose.apply(0, 0), (-1, -1));
+    }
+}
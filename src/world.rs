@@ -1,51 +1,1356 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use crate::escape::Ships;
+use crate::escape::escaping_ships;
+use crate::rule_set::Edges;
+use crate::rule_set::Phases;
 use crate::rule_set::RuleSet;
+use crate::rule_set::RuleTopology;
+use crate::transform;
+use crate::transform::Orientation;
+use crate::transform::Transform;
 
 use crate::cell::Cell;
+use crate::cell::CellBuf;
+use crate::cell::LEAF_MASK;
 use crate::WorldOffset;
 
+/// Once the buffer holds this many cells, it is compacted the next time the world is stepped
+const MIN_COMPACT_LEN: usize = 1 << 20;
+
+/// A glider heading south east, which tests start from
+#[cfg(test)]
+pub(crate) const GLIDER: [(WorldOffset, WorldOffset); 5] = [(1, 2), (2, 1), (0, 0), (1, 0), (2, 0)];
+
+/// A Life world holding only a [`GLIDER`]
+#[cfg(test)]
+pub(crate) fn glider() -> World {
+    let mut world = World::new(crate::rule_set::B3S23);
+
+    for (x, y) in GLIDER {
+        world.set(x, y);
+    }
+
+    world
+}
+
+/// A rectangular region of the world.
+///
+/// `(x, y)` is the top left cell of the region, which extends `w` cells to the east (increasing
+/// `x`) and `h` cells to the south (decreasing `y`). This is the same orientation the [`Camera`]
+/// draws with.
+///
+/// [`Camera`]: crate::camera::Camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: WorldOffset,
+    pub y: WorldOffset,
+    pub w: u64,
+    pub h: u64,
+}
+
+impl Rect {
+    pub const fn new(x: WorldOffset, y: WorldOffset, w: u64, h: u64) -> Self {
+        Self { x, y, w, h }
+    }
+
+    /// Check whether the cell at (`x`, `y`) lies inside the region
+    pub fn contains(&self, x: WorldOffset, y: WorldOffset) -> bool {
+        self.x <= x
+            && x < self.x + self.w as WorldOffset
+            && y <= self.y
+            && y > self.y - self.h as WorldOffset
+    }
+
+    /// Check whether the square of sidelength `size` whose top left cell is (`x`, `y`) shares
+    /// any cell with the region
+    pub fn overlaps(&self, x: WorldOffset, y: WorldOffset, size: WorldOffset) -> bool {
+        x < self.x + self.w as WorldOffset
+            && self.x < x + size
+            && y > self.y - self.h as WorldOffset
+            && self.y > y - size
+    }
+}
+
+/// How a pattern repeats itself, see [`World::detect_period`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Periodicity {
+    /// Number of generations after which the pattern repeats
+    pub period: u64,
+
+    /// Displacement of the pattern after every period, with `y` increasing to the north
+    pub dx: WorldOffset,
+    pub dy: WorldOffset,
+
+    /// Number of generations before the pattern starts repeating, which is 0 unless it is still
+    /// settling down
+    pub start: u64,
+}
+
+impl Periodicity {
+    /// A pattern that never changes, which includes the empty one
+    pub fn is_still_life(&self) -> bool {
+        self.period == 1 && !self.is_spaceship()
+    }
+
+    /// A pattern that changes, but comes back in place
+    pub fn is_oscillator(&self) -> bool {
+        self.period > 1 && !self.is_spaceship()
+    }
+
+    /// A pattern that comes back displaced
+    pub fn is_spaceship(&self) -> bool {
+        self.dx != 0 || self.dy != 0
+    }
+}
+
+/// When and how a pattern settles down, see [`World::run_until_stable`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stabilisation {
+    /// The first generation the pattern repeats from
+    pub generation: u64,
+
+    /// Number of live cells at that generation. Escaping spaceships are counted in, unless they
+    /// were removed by [`World::run_until_stable`], in which case they are left out.
+    pub population: u64,
+
+    pub periodicity: Periodicity,
+}
+
+/// Cells that differ between two worlds, see [`World::diff`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffResult {
+    /// Cells dead in the first world and alive in the second one
+    pub births: Vec<(WorldOffset, WorldOffset)>,
+
+    /// Cells alive in the first world and dead in the second one
+    pub deaths: Vec<(WorldOffset, WorldOffset)>,
+}
+
+/// How the cells of two worlds are combined, see [`World::paste`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Cells alive in either world
+    Or,
+
+    /// Cells alive in both worlds
+    And,
+
+    /// Cells alive in exactly one of the worlds
+    Xor,
+
+    /// Cells of the pasted world within its bounding box, and cells of this world elsewhere
+    Copy,
+}
+
+/// Cells to look for with [`World::find`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pattern {
+    /// Cells that have to be alive, in any state but 0
+    pub live: Vec<(WorldOffset, WorldOffset)>,
+
+    /// Cells that have to be dead, like the envelope around a catalyst
+    pub dead: Vec<(WorldOffset, WorldOffset)>,
+}
+
+impl Pattern {
+    /// The live cells of `world`, with no cells that have to be dead
+    pub fn new(world: &World) -> Self {
+        Self {
+            live: world.live_cells().collect(),
+            dead: Vec::new(),
+        }
+    }
+
+    /// Require the live cells of `envelope` to be dead, except for the cells of the pattern
+    pub fn with_envelope(mut self, envelope: &World) -> Self {
+        self.dead = envelope
+            .live_cells()
+            .filter(|cell| !self.live.contains(cell))
+            .collect();
+
+        self
+    }
+}
+
+/// Key of a normalised state, see [`World::insert_normalised`]
+type Key = ((usize, u8), u8);
+
+/// States of a pattern seen within the last `check_period` generations, each moved to the top
+/// left corner of a world of its own, see [`World::insert_normalised`]
+struct History<T> {
+    normalised: World,
+
+    /// The generation each state was last seen at, along with `T`
+    seen: HashMap<Key, (u64, T)>,
+
+    /// States in the order they were seen, along with their generation
+    window: VecDeque<(Key, u64)>,
+    check_period: u64,
+}
+
+impl<T: Copy> History<T> {
+    fn new(rule: RuleSet, check_period: u64) -> Self {
+        Self {
+            normalised: World::new(rule),
+            seen: HashMap::new(),
+            window: VecDeque::new(),
+            check_period,
+        }
+    }
+
+    /// Look for `cells`, whose bounding box is `bbox`, among the states seen in the same phase
+    /// within `check_period` generations before `generation`, and remember them with `value`
+    /// otherwise.
+    ///
+    /// Once large enough, the buffer of the normalised states is compacted, keeping only the
+    /// states still remembered.
+    fn check<I>(
+        &mut self,
+        cells: I,
+        bbox: Rect,
+        phase: u8,
+        generation: u64,
+        value: T,
+    ) -> Option<(u64, T)>
+    where
+        I: Iterator<Item = (WorldOffset, WorldOffset, u8)>,
+    {
+        let key = (self.normalised.insert_normalised(cells, bbox), phase);
+
+        if let Some(&seen) = self.seen.get(&key) {
+            return Some(seen);
+        }
+
+        self.seen.insert(key, (generation, value));
+        self.window.push_back((key, generation));
+
+        while let Some(&(key, seen)) = self.window.front()
+            && seen.saturating_add(self.check_period) <= generation
+        {
+            self.window.pop_front();
+
+            if self.seen.get(&key).is_some_and(|&(g, _)| g == seen) {
+                self.seen.remove(&key);
+            }
+        }
+
+        let normalised = &mut self.normalised;
+        if normalised.buf.len() > normalised.compact_len {
+            let roots: Vec<_> = self.window.iter().map(|&((root, _), _)| root.0).collect();
+            let (buf, compacted) = normalised.buf.compact(&roots);
+            normalised.buf = buf;
+            normalised.compact_len = MIN_COMPACT_LEN.max(2 * normalised.buf.len());
+
+            let moved: HashMap<_, _> = roots.into_iter().zip(compacted).collect();
+            let key = |((root, depth), phase): Key| ((moved[&root], depth), phase);
+
+            let seen = std::mem::take(&mut self.seen);
+            self.seen = seen.into_iter().map(|(k, v)| (key(k), v)).collect();
+
+            let window = std::mem::take(&mut self.window);
+            self.window = window.into_iter().map(|(k, g)| (key(k), g)).collect();
+        }
+
+        None
+    }
+}
+
 pub struct World {
-    /// Life rules
+    rule: RuleSet,
+
+    /// Life rules, for each phase of the rule
     ///
-    /// Indexing into this array with rule `r` yields the result of `r`.
-    rules: Vec<u16>,
+    /// Indexing into the table of a phase with rule `r` yields the result of `r`.
+    rules: Phases,
 
     /// Index of the root [`Cell`] in `buf`
     pub root: usize,
 
     /// This is where all of our memory goes
-    pub buf: Vec<Cell>,
+    pub buf: CellBuf,
 
     /// World depth, where `3` is a leaf [`Cell`], (8x8 world size).
     ///
     /// In general, `n` yields a world sidelength of `2^n`
     pub depth: u8,
+
+    /// Number of generations computed so far
+    generation: u64,
+
+    /// Length of `buf` past which it gets compacted
+    compact_len: usize,
 }
 
 impl World {
     /// Create an empty new world
     pub fn new(rule: RuleSet) -> Self {
-        let rules = rule.compute_rules();
+        let buf = CellBuf::with_states(rule.states());
+
+        Self::from_parts(rule, buf, 0, 3, 0)
+    }
 
-        // First cell is the canonical void cell, second is the root, an uninitialized leaf
-        let buf = vec![Cell::void(), Cell::leaf_uninit()];
+    /// Create a world out of an existing buffer, whose root is the `2^depth` cell at `root`
+    pub(crate) fn from_parts(
+        rule: RuleSet,
+        buf: CellBuf,
+        root: usize,
+        depth: u8,
+        generation: u64,
+    ) -> Self {
+        assert_eq!(
+            buf.states(),
+            rule.states(),
+            "The buffer should hold cells of the rule"
+        );
 
-        let root = 1;
+        let rules = rule.compute_phases();
 
         Self {
+            rule,
             rules,
             root,
+            compact_len: MIN_COMPACT_LEN.max(2 * buf.len()),
             buf,
-            depth: 3,
+            depth,
+            generation,
+        }
+    }
+
+    pub fn rule(&self) -> &RuleSet {
+        &self.rule
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether every cell outside of the world is alive, which only happens with B0 rules. In
+    /// that case, the world holds the complement of its live cells.
+    ///
+    /// See: [`RuleSet::compute_phases`]
+    pub fn background(&self) -> bool {
+        self.rules.background(self.phase())
+    }
+
+    /// Phase of the rule at the current generation
+    fn phase(&self) -> u8 {
+        self.rules.phase_at(self.generation)
+    }
+
+    pub fn next(&mut self) {
+        let mut root = self.buf[self.root];
+        let phase = self.phase();
+
+        self.generation += 1 << (self.depth - 3);
+
+        let root = root.next(&self.rules, phase, &mut self.buf);
+        self.set_root(root, self.depth - 1);
+
+        self.grow(1);
+    }
+
+    /// Advance the world by exactly `gens` generations, growing it as needed so that no cell is
+    /// lost to its edges.
+    ///
+    /// With a bounded topology, like a torus, cells stay within the [`World::grid`] instead.
+    pub fn step(&mut self, gens: u64) {
+        if self.grid().is_some() {
+            self.step_bounded(gens);
+            return;
+        }
+
+        for j in 0..u64::BITS as u8 {
+            if gens & (1 << j) != 0 {
+                self.step_pow2(j);
+            }
+        }
+    }
+
+    /// Advance the world by `2^j` generations
+    fn step_pow2(&mut self, j: u8) {
+        // A 2^n cell yields its center 2^{n - 1} cell after at most 2^{n - 3} generations. In that
+        // time, nothing within the center 2^{n - 2} cell can travel far enough to be cut off.
+        while self.depth < j + 4 || !self.is_centered() {
+            self.grow(1);
+        }
+
+        let mut root = self.buf[self.root];
+
+        let phase = self.phase();
+        let root = root.step(self.depth, j, &self.rules, phase, &mut self.buf);
+        self.set_root(root, self.depth - 1);
+
+        self.generation += 1 << j;
+
+        if self.buf.len() > self.compact_len {
+            self.compact();
+        }
+    }
+
+    /// The region cells are confined to by the topology of the rule, or `None` on an unbounded
+    /// plane. On a bounded plane, cells outside of it are dead for good. Like in Golly, a `w` by
+    /// `h` grid spans `-(w / 2)..w - w / 2` horizontally and `h / 2 - h + 1..h / 2 + 1`
+    /// vertically, so that it is centered on the origin, with `y` increasing to the north.
+    ///
+    /// A dimension of size 0 is unbounded, in which case the grid spans the whole world along it.
+    /// Only planes and tori can be unbounded.
+    ///
+    /// See: [`RuleExtension`](crate::rule_set::RuleExtension)
+    pub fn grid(&self) -> Option<Rect> {
+        let ext = self.rule.extension()?;
+
+        let unbounded = match ext.topology {
+            RuleTopology::Planar | RuleTopology::Torus => ext.width == 0 && ext.height == 0,
+            _ => ext.width == 0 || ext.height == 0,
+        };
+
+        if unbounded {
+            return None;
+        }
+
+        let half = 1 << (self.depth - 1);
+
+        let (x, w) = match ext.width as WorldOffset {
+            0 => (-half, 2 * half),
+            w => (-(w / 2), w),
+        };
+
+        let (y, h) = match ext.height as WorldOffset {
+            0 => (half - 1, 2 * half),
+            h => (h / 2, h),
+        };
+
+        Some(Rect::new(x, y, w as u64, h as u64))
+    }
+
+    /// Like [`World::step`], within the [`World::grid`].
+    ///
+    /// Each iteration, a cell only sees as far as its neighbours. So after copying `n` cells past
+    /// the edges of the grid from where they wrap around to, every cell of the grid can be
+    /// stepped by `n` generations at once, as long as `n` is no bigger than the grid. The copies
+    /// are then thrown away.
+    ///
+    /// Nothing wraps around a bounded plane, where cells past the edges can't come to life. On a
+    /// sphere or a cross-surface, the cells past an edge don't lie next to each other in the grid
+    /// once far enough away from it. Those are stepped a generation at a time.
+    fn step_bounded(&mut self, mut gens: u64) {
+        let Some(ext) = self.rule.extension() else {
+            return;
+        };
+
+        let margin = match ext.topology {
+            RuleTopology::Planar | RuleTopology::Spherical | RuleTopology::CrossSurface => 1,
+            RuleTopology::Torus | RuleTopology::KleinBottle => [ext.width, ext.height]
+                .into_iter()
+                .filter(|&n| n > 0)
+                .min()
+                .unwrap_or(1) as u64,
+        };
+
+        self.clear_outside_grid();
+
+        while gens > 0 {
+            let j = gens.min(margin).ilog2() as u8;
+
+            self.fill_edges(1 << j);
+            self.step_pow2(j);
+            self.clear_outside_grid();
+
+            gens -= 1 << j;
+        }
+    }
+
+    /// Fill in the `n` cells past the edges of the [`World::grid`], by copying the cells within
+    /// `n` cells of the edges to where they wrap around to, past the other edges. On a bounded
+    /// plane, `n` is 1 and the cells past the edges are dead.
+    fn fill_edges(&mut self, n: u64) {
+        let Some(grid) = self.grid() else {
+            return;
+        };
+
+        let Some(ext) = self.rule.extension() else {
+            return;
+        };
+
+        let (topology, twist, shift) = (ext.topology, ext.twist, ext.shift);
+        let (w, h) = (ext.width as WorldOffset, ext.height as WorldOffset);
+        let n = n as WorldOffset;
+
+        // The grid, along with the cells copied past its bounded edges
+        let (nx, ny) = (if w > 0 { n } else { 0 }, if h > 0 { n } else { 0 });
+        let outer = Rect::new(
+            grid.x - nx,
+            grid.y + ny,
+            grid.w + 2 * nx as u64,
+            grid.h + 2 * ny as u64,
+        );
+
+        // Which must fit in the world
+        let reach = [
+            -outer.x,
+            outer.x + outer.w as WorldOffset,
+            outer.y + 1,
+            outer.h as WorldOffset - outer.y - 1,
+        ];
+        while 1 << (self.depth - 1) < reach.into_iter().max().unwrap_or_default() {
+            self.grow(1);
+        }
+
+        if let RuleTopology::Planar = topology {
+            // Dead cells are stored as live ones while the background is on, see
+            // `World::background`. Otherwise, cleared cells are already dead.
+            if self.background() {
+                let (left, right) = (outer.x, outer.x + outer.w as WorldOffset - 1);
+                let (top, bottom) = (outer.y, outer.y - outer.h as WorldOffset + 1);
+
+                if w > 0 {
+                    for y in bottom..=top {
+                        self.set(left, y);
+                        self.set(right, y);
+                    }
+                }
+
+                if h > 0 {
+                    for x in left..=right {
+                        self.set(x, top);
+                        self.set(x, bottom);
+                    }
+                }
+            }
+
+            return;
+        }
+
+        let mut strips = Vec::new();
+        if w > 0 {
+            strips.push(Rect::new(grid.x, grid.y, n as u64, grid.h));
+            strips.push(Rect::new(grid.x + w - n, grid.y, n as u64, grid.h));
+        }
+        if h > 0 {
+            strips.push(Rect::new(grid.x, grid.y, grid.w, n as u64));
+            strips.push(Rect::new(grid.x, grid.y - h + n, grid.w, n as u64));
+        }
+
+        let mut cells: Vec<_> = strips
+            .iter()
+            .flat_map(|&strip| self.live_cells_in(strip))
+            .collect();
+        cells.sort();
+        cells.dedup();
+
+        // Copies are placed by column `c` from the left and row `r` from the top of the grid
+        let (sx, sy) = match shift {
+            Some((Edges::Horizontal, s)) => (s as WorldOffset % w, 0),
+            Some((Edges::Vertical, s)) => (0, s as WorldOffset % h),
+            None => (0, 0),
+        };
+
+        let mut copies = Vec::new();
+        for (x, y) in cells {
+            let state = self.state(x, y);
+            let (c, r) = (x - grid.x, grid.y - y);
+
+            match topology {
+                RuleTopology::Planar => {}
+                // Wrapping around `a` times horizontally and `b` times vertically. On a Klein
+                // bottle, every other wrap across the twisted edges is mirrored, and the shift
+                // moves the mirror instead.
+                RuleTopology::Torus | RuleTopology::KleinBottle => {
+                    let range = |size| if size > 0 { -2..=2 } else { 0..=0 };
+
+                    for a in range(w) {
+                        for b in range(h) {
+                            let (c, r) = match twist {
+                                None => (c + b * sx, r - a * sy),
+                                Some(Edges::Horizontal) if b % 2 != 0 => (w - 1 + sx - c, r),
+                                Some(Edges::Vertical) if a % 2 != 0 => (c, h - 1 + sy - r),
+                                Some(_) => (c, r),
+                            };
+
+                            copies.push((c + a * w, r - b * h, state));
+                        }
+                    }
+                }
+                // Only the cells right past the edges are filled, along with the corners
+                RuleTopology::Spherical | RuleTopology::CrossSurface => {
+                    let glued = |c, r| match topology {
+                        RuleTopology::Spherical => [
+                            (c == 0, (r, -1)),
+                            (r == 0, (-1, c)),
+                            (c == w - 1, (r, h)),
+                            (r == h - 1, (w, c)),
+                        ],
+                        _ => [
+                            (r == h - 1, (w - 1 - c, -1)),
+                            (r == 0, (w - 1 - c, h)),
+                            (c == w - 1, (-1, h - 1 - r)),
+                            (c == 0, (w, h - 1 - r)),
+                        ],
+                    };
+
+                    for (on_edge, (c, r)) in glued(c, r) {
+                        if on_edge {
+                            copies.push((c, r, state));
+                        }
+                    }
+
+                    let (left, top) = (c == 0, r == 0);
+                    let (right, bottom) = (c == w - 1, r == h - 1);
+                    if (left || right) && (top || bottom) {
+                        let dc = if left { -1 } else { 1 };
+                        let dr = if top { -1 } else { 1 };
+
+                        copies.push((c + dc, r + dr, state));
+                    }
+                }
+            }
+        }
+
+        for (c, r, state) in copies {
+            let (x, y) = (grid.x + c, grid.y - r);
+
+            if !grid.contains(x, y) && outer.contains(x, y) {
+                self.set_state(x, y, state);
+            }
+        }
+    }
+
+    /// Clear every cell outside of the [`World::grid`]
+    fn clear_outside_grid(&mut self) {
+        if let Some(grid) = self.grid() {
+            self.clear_outside(grid);
+        }
+    }
+
+    /// Clear every cell of `rect`. Only the cells of the quadtree crossing its edges are rebuilt.
+    pub fn clear_rect(&mut self, rect: Rect) {
+        let half = 1 << (self.depth - 1);
+
+        self.root = self.clear(self.root, self.depth, -half, half - 1, rect, false);
+    }
+
+    /// Clear every cell outside of `rect`. Only the cells of the quadtree crossing its edges are
+    /// rebuilt.
+    pub fn clear_outside(&mut self, rect: Rect) {
+        let half = 1 << (self.depth - 1);
+
+        self.root = self.clear(self.root, self.depth, -half, half - 1, rect, true);
+    }
+
+    /// A copy of the cells of `rect`, at the same coordinates in a world of their own, at the
+    /// same generation. The cells of the quadtree within `rect` are copied over as they are, and
+    /// only the cells crossing its edges are rebuilt.
+    pub fn extract(&self, rect: Rect) -> World {
+        let mut buf = CellBuf::with_states(self.buf.states());
+        let mut map = HashMap::from([(0, 0)]);
+
+        let half = 1 << (self.depth - 1);
+        let (x, y) = (-half, half - 1);
+        let root = self.extract_cell(self.root, self.depth, x, y, rect, &mut buf, &mut map);
+
+        World::from_parts(self.rule.clone(), buf, root, self.depth, self.generation)
+    }
+
+    /// Copy the cells of `rect` in the `2^depth` cell at `ptr`, whose top left cell is (`x`, `y`),
+    /// into `buf` like [`CellBuf::copy_into`], and return the index of the copy
+    #[allow(clippy::too_many_arguments)]
+    fn extract_cell(
+        &self,
+        ptr: usize,
+        depth: u8,
+        x: WorldOffset,
+        y: WorldOffset,
+        rect: Rect,
+        buf: &mut CellBuf,
+        map: &mut HashMap<usize, usize>,
+    ) -> usize {
+        let cell = self.buf[ptr];
+        let size = 1 << depth;
+
+        if cell.is_void() || !rect.overlaps(x, y, size) {
+            return 0;
+        }
+
+        if rect.contains(x, y) && rect.contains(x + size - 1, y - size + 1) {
+            return self.buf.copy_into(ptr, buf, map);
+        }
+
+        if cell.is_leaf() {
+            let states = self.buf.leaf_states(cell);
+
+            return buf.insert_leaf(std::array::from_fn(|row| {
+                std::array::from_fn(|col| {
+                    let inside = rect.contains(x + col as WorldOffset, y - row as WorldOffset);
+
+                    if inside { states[row][col] } else { 0 }
+                })
+            }));
+        }
+
+        let half = size >> 1;
+
+        let nw = self.extract_cell(cell.nw, depth - 1, x, y, rect, buf, map);
+        let ne = self.extract_cell(cell.ne, depth - 1, x + half, y, rect, buf, map);
+        let sw = self.extract_cell(cell.sw, depth - 1, x, y - half, rect, buf, map);
+        let se = self.extract_cell(cell.se, depth - 1, x + half, y - half, rect, buf, map);
+
+        buf.insert(Cell::new(nw, ne, sw, se))
+    }
+
+    /// Clear the cells of `rect` in the `2^depth` cell at `ptr`, whose top left cell is
+    /// (`x`, `y`), or the cells outside of it if `outside` is set. Returns the index of the
+    /// resulting cell, where the cells left untouched are reused.
+    fn clear(
+        &mut self,
+        ptr: usize,
+        depth: u8,
+        x: WorldOffset,
+        y: WorldOffset,
+        rect: Rect,
+        outside: bool,
+    ) -> usize {
+        let cell = self.buf[ptr];
+        let size = 1 << depth;
+
+        if cell.is_void() {
+            return 0;
+        }
+
+        if !rect.overlaps(x, y, size) {
+            return if outside { 0 } else { ptr };
+        }
+
+        if rect.contains(x, y) && rect.contains(x + size - 1, y - size + 1) {
+            return if outside { ptr } else { 0 };
+        }
+
+        if cell.is_leaf() {
+            let states = self.buf.leaf_states(cell);
+
+            return self.buf.insert_leaf(std::array::from_fn(|row| {
+                std::array::from_fn(|col| {
+                    let inside = rect.contains(x + col as WorldOffset, y - row as WorldOffset);
+                    let kept = inside == outside;
+
+                    if kept { states[row][col] } else { 0 }
+                })
+            }));
+        }
+
+        let half = size >> 1;
+
+        let nw = self.clear(cell.nw, depth - 1, x, y, rect, outside);
+        let ne = self.clear(cell.ne, depth - 1, x + half, y, rect, outside);
+        let sw = self.clear(cell.sw, depth - 1, x, y - half, rect, outside);
+        let se = self.clear(cell.se, depth - 1, x + half, y - half, rect, outside);
+
+        self.buf.insert(Cell::new(nw, ne, sw, se))
+    }
+
+    /// Replace the root with the cell at `ptr`, of sidelength `2^depth`
+    fn set_root(&mut self, ptr: usize, depth: u8) {
+        self.root = ptr;
+        self.depth = depth;
+    }
+
+    /// Step a copy of the world a generation at a time, until its pattern comes back to a
+    /// previous state up to translation, for at most `max_gens` generations.
+    ///
+    /// Each generation, the pattern is moved so that its bounding box lies in the top left
+    /// corner of an empty world of its own. Since cells are hash-consed, two patterns are then
+    /// the same up to translation exactly when they end up with the same root.
+    ///
+    /// Transient phases are skipped over, so a pattern that settles into an oscillator is
+    /// reported as one, along with when it starts to oscillate.
+    pub fn detect_period(&self, max_gens: u64) -> Option<Periodicity> {
+        let (buf, roots) = self.buf.compact(&[self.root]);
+        let mut world = World::from_parts(
+            self.rule.clone(),
+            buf,
+            roots[0],
+            self.depth,
+            self.generation,
+        );
+
+        let mut history = History::new(self.rule.clone(), u64::MAX);
+
+        for generation in 0..=max_gens {
+            let bbox = world.bounding_box().unwrap_or(Rect::new(0, 0, 0, 0));
+            let cells = world
+                .live_cells_in(bbox)
+                .map(|(x, y)| (x, y, world.state(x, y)));
+
+            if let Some((start, (x, y))) =
+                history.check(cells, bbox, world.phase(), generation, (bbox.x, bbox.y))
+            {
+                return Some(Periodicity {
+                    period: generation - start,
+                    dx: bbox.x - x,
+                    dy: bbox.y - y,
+                    start,
+                });
+            }
+
+            if generation < max_gens {
+                world.step(1);
+            }
+        }
+
+        None
+    }
+
+    /// Replace the world with `cells`, whose bounding box is `bbox`, moved to the top left
+    /// corner of the smallest world they fit in, and return the root and depth of the result.
+    ///
+    /// The world is built from the leaves up, so that only the cells of the result get inserted
+    /// into the buffer.
+    fn insert_normalised<I>(&mut self, cells: I, bbox: Rect) -> (usize, u8)
+    where
+        I: Iterator<Item = (WorldOffset, WorldOffset, u8)>,
+    {
+        let size = bbox.w.max(bbox.h).max(8);
+        let depth = size.next_power_of_two().ilog2() as u8;
+
+        // The states of every leaf, by its column and row from the top left
+        let mut leaves: HashMap<(u64, u64), [[u8; 8]; 8]> = HashMap::new();
+
+        for (x, y, state) in cells {
+            let (col, row) = ((x - bbox.x) as u64, (bbox.y - y) as u64);
+            let states = leaves.entry((col >> 3, row >> 3)).or_default();
+
+            states[row as usize & 7][col as usize & 7] = state;
+        }
+
+        let mut level: HashMap<_, _> = leaves
+            .into_iter()
+            .map(|(pos, states)| (pos, self.buf.insert_leaf(states)))
+            .collect();
+
+        for _ in 3..depth {
+            let mut quadrants: HashMap<(u64, u64), [usize; 4]> = HashMap::new();
+
+            for ((col, row), ptr) in level {
+                let quadrant = 2 * (row & 1) + (col & 1);
+                quadrants.entry((col >> 1, row >> 1)).or_default()[quadrant as usize] = ptr;
+            }
+
+            level = quadrants
+                .into_iter()
+                .map(|(pos, [nw, ne, sw, se])| (pos, self.buf.insert(Cell::new(nw, ne, sw, se))))
+                .collect();
+        }
+
+        self.root = level.get(&(0, 0)).copied().unwrap_or(0);
+        self.depth = depth;
+
+        (self.root, self.depth)
+    }
+
+    /// Step the world a generation at a time, until it repeats a state from at most
+    /// `check_period` generations before up to translation, for at most `max_gens` generations.
+    ///
+    /// Spaceships flying away from every other live cell never come back, so they are left out
+    /// of the comparison. They are kept in the world, unless `remove_escaping` is set, in which
+    /// case they are deleted as soon as they are found, like with
+    /// [`crate::escape::remove_escaping`]. This is how a methuselah like the R-pentomino
+    /// stabilises at generation 1103, even though its gliders keep going. Just like
+    /// [`World::detect_period`], states are compared once moved to the corner of a world of
+    /// their own.
+    ///
+    /// The world is left one period past the generation it stabilised at, which is returned
+    /// along with the population at that generation.
+    pub fn run_until_stable(
+        &mut self,
+        max_gens: u64,
+        check_period: u64,
+        remove_escaping: bool,
+    ) -> Option<Stabilisation> {
+        let first = self.generation;
+        let ships = Ships::new(&self.rule);
+
+        let mut history = History::new(self.rule.clone(), check_period);
+
+        loop {
+            let live: Vec<_> = self.live_cells().collect();
+            let escaping: HashSet<_> = escaping_ships(&live, &ships)
+                .into_iter()
+                .flat_map(|ship| ship.cells)
+                .collect();
+
+            let cells: Vec<_> = live
+                .iter()
+                .filter(|cell| !escaping.contains(cell))
+                .map(|&(x, y)| (x, y, self.state(x, y)))
+                .collect();
+
+            if remove_escaping {
+                for &(x, y) in &escaping {
+                    self.set_state(x, y, 0);
+                }
+            }
+
+            let population = if remove_escaping {
+                cells.len()
+            } else {
+                live.len()
+            } as u64;
+
+            let bbox = match cells.first() {
+                None => Rect::new(0, 0, 0, 0),
+                Some(&(x, y, _)) => {
+                    let (mut x0, mut x1, mut y0, mut y1) = (x, x, y, y);
+                    for &(x, y, _) in &cells {
+                        (x0, x1) = (x0.min(x), x1.max(x));
+                        (y0, y1) = (y0.min(y), y1.max(y));
+                    }
+
+                    Rect::new(x0, y1, (x1 - x0 + 1) as u64, (y1 - y0 + 1) as u64)
+                }
+            };
+
+            let value = (bbox.x, bbox.y, population);
+            let phase = self.phase();
+
+            if let Some((generation, (x, y, population))) =
+                history.check(cells.into_iter(), bbox, phase, self.generation, value)
+            {
+                return Some(Stabilisation {
+                    generation,
+                    population,
+                    periodicity: Periodicity {
+                        period: self.generation - generation,
+                        dx: bbox.x - x,
+                        dy: bbox.y - y,
+                        start: generation - first,
+                    },
+                });
+            }
+
+            if self.generation - first >= max_gens {
+                return None;
+            }
+
+            self.step(1);
+        }
+    }
+
+    /// The cells born and the cells that died going from this world to `other`, like from a
+    /// generation to a later one. With Generations rules, cells are alive in any state but 0.
+    ///
+    /// Both worlds are copied into the same buffer first, where equal regions are hash-consed
+    /// into equal cells, so the regions that didn't change are skipped over as a whole.
+    pub fn diff(&self, other: &World) -> DiffResult {
+        assert_eq!(
+            self.buf.states(),
+            other.buf.states(),
+            "Both worlds should have the same number of states"
+        );
+
+        let mut buf = CellBuf::with_states(self.buf.states());
+        let depth = self.depth.max(other.depth);
+
+        let [a, b] = [self, other].map(|world| {
+            let mut map = HashMap::from([(0, 0)]);
+            let mut ptr = world.buf.copy_into(world.root, &mut buf, &mut map);
+
+            for _ in world.depth..depth {
+                let cell = buf[ptr];
+                let cell = cell.grow(&mut buf);
+                ptr = buf.insert(cell);
+            }
+
+            ptr
+        });
+
+        let half = 1 << (depth - 1);
+
+        let mut diff = DiffResult::default();
+        Self::diff_cells(&buf, a, b, depth, -half, half - 1, &mut diff);
+
+        diff.births.sort();
+        diff.deaths.sort();
+
+        diff
+    }
+
+    /// Add the cells that differ between `a` and `b` to `diff`, where both are `2^depth` cells
+    /// wide with (`x`, `y`) as their top left cell
+    fn diff_cells(
+        buf: &CellBuf,
+        a: usize,
+        b: usize,
+        depth: u8,
+        x: WorldOffset,
+        y: WorldOffset,
+        diff: &mut DiffResult,
+    ) {
+        if a == b {
+            return;
+        }
+
+        let (a, b) = (buf[a], buf[b]);
+
+        if depth == 3 {
+            let (a, b) = (buf.leaf_states(a), buf.leaf_states(b));
+
+            for row in 0..8 {
+                for col in 0..8 {
+                    let cell = (x + col as WorldOffset, y - row as WorldOffset);
+
+                    match (a[row][col] != 0, b[row][col] != 0) {
+                        (false, true) => diff.births.push(cell),
+                        (true, false) => diff.deaths.push(cell),
+                        _ => {}
+                    }
+                }
+            }
+
+            return;
+        }
+
+        let (Some(a), Some(b)) = (a.children(), b.children()) else {
+            unreachable!("Only cells 8 cells wide are leaves");
+        };
+
+        let half = 1 << (depth - 1);
+        let corners = [(x, y), (x + half, y), (x, y - half), (x + half, y - half)];
+
+        for ((a, b), (x, y)) in a.into_iter().zip(b).zip(corners) {
+            Self::diff_cells(buf, a, b, depth - 1, x, y, diff);
+        }
+    }
+
+    /// Rotate or mirror the world about the origin, then move it, growing the world as needed
+    /// so that no cell is lost.
+    ///
+    /// This works on the quadtree rather than on every cell: quadrants are swapped around
+    /// recursively, and moved cells are pieced together from the cells they overlap, which are
+    /// reused whenever they line up.
+    pub fn transform(&mut self, transform: Transform) {
+        let mut memo = HashMap::new();
+        self.root = transform::orient(&mut self.buf, self.root, transform.orientation, &mut memo);
+
+        self.translate(transform.dx, transform.dy);
+    }
+
+    /// Move every cell `dx` cells to the east and `dy` cells to the north
+    fn translate(&mut self, dx: WorldOffset, dy: WorldOffset) {
+        if (dx, dy) == (0, 0) {
+            return;
+        }
+
+        let half = 1 << (self.depth - 1);
+        while 1 << (self.depth - 1) < half + dx.abs().max(dy.abs()) {
+            self.grow(1);
+        }
+
+        self.root = self.translated(self.root, self.depth, dx, dy);
+    }
+
+    /// Move every cell of the `2^depth` cell at `ptr` by `dx` cells to the east and `dy` cells to
+    /// the north, and return the index of the resulting cell. Cells moved out of it are lost.
+    fn translated(&mut self, ptr: usize, depth: u8, dx: WorldOffset, dy: WorldOffset) -> usize {
+        // The new cell lies `-dx` cells to the east and `dy` cells to the south of the old one,
+        // which is placed in a square of 4 cells so that the new one fits within it
+        let size = 1 << depth;
+        let (col, ox) = if dx <= 0 { (0, -dx) } else { (1, size - dx) };
+        let (row, oy) = if dy >= 0 { (0, dy) } else { (1, size + dy) };
+
+        if ox < 0 || ox >= size || oy < 0 || oy >= size {
+            return 0;
+        }
+
+        let mut quadrants = [0; 4];
+        quadrants[row * 2 + col] = ptr;
+
+        let mut memo = HashMap::new();
+        transform::window(&mut self.buf, quadrants, depth, ox, oy, &mut memo)
+    }
+
+    /// Combine the cells of `other`, moved by `dx` cells to the east and `dy` cells to the north,
+    /// with the cells of this world, growing it as needed. With Generations rules, cells are
+    /// alive in any state but 0, and keep the state they had in `other` unless
+    /// [`Mode::And`] keeps the state they had in this world.
+    ///
+    /// This works on the quadtree rather than on every cell: `other` is copied over, moved like
+    /// with [`World::transform`], and combined cell by cell of the quadtree, where void and equal
+    /// cells are resolved right away.
+    pub fn paste(&mut self, other: &World, dx: WorldOffset, dy: WorldOffset, mode: Mode) {
+        assert_eq!(
+            self.buf.states(),
+            other.buf.states(),
+            "Both worlds should have the same number of states"
+        );
+
+        let reach = (1 << (other.depth - 1)) + dx.abs().max(dy.abs());
+        while 1 << (self.depth - 1) < reach {
+            self.grow(1);
+        }
+
+        let mut map = HashMap::from([(0, 0)]);
+        let mut pasted = other.buf.copy_into(other.root, &mut self.buf, &mut map);
+
+        for _ in other.depth..self.depth {
+            let cell = self.buf[pasted];
+            let cell = cell.grow(&mut self.buf);
+            pasted = self.buf.insert(cell);
+        }
+
+        pasted = self.translated(pasted, self.depth, dx, dy);
+
+        // Copying clears the bounding box of `other` first, so it is the only thing left there
+        let mode = match (mode, other.bounding_box()) {
+            (Mode::Copy, Some(rect)) => {
+                self.clear_rect(Rect::new(rect.x + dx, rect.y + dy, rect.w, rect.h));
+
+                Mode::Or
+            }
+            (Mode::Copy, None) => return,
+            (mode, _) => mode,
+        };
+
+        let mut memo = HashMap::new();
+        self.root = self.combine(self.root, pasted, self.depth, mode, &mut memo);
+    }
+
+    /// Combine the `2^depth` cells at `a` and `b` like [`World::paste`], and return the index of
+    /// the resulting cell
+    fn combine(
+        &mut self,
+        a: usize,
+        b: usize,
+        depth: u8,
+        mode: Mode,
+        memo: &mut HashMap<(usize, usize), usize>,
+    ) -> usize {
+        match mode {
+            _ if a == b && mode != Mode::Xor => return a,
+            Mode::Xor if a == b => return 0,
+            Mode::And if a == 0 || b == 0 => return 0,
+            Mode::Or | Mode::Xor | Mode::Copy if a == 0 => return b,
+            Mode::Or | Mode::Xor | Mode::Copy if b == 0 => return a,
+            _ => {}
+        }
+
+        if let Some(&n) = memo.get(&(a, b)) {
+            return n;
+        }
+
+        let (cell_a, cell_b) = (self.buf[a], self.buf[b]);
+
+        let n = if depth == 3 {
+            let (a, b) = (self.buf.leaf_states(cell_a), self.buf.leaf_states(cell_b));
+
+            self.buf.insert_leaf(std::array::from_fn(|row| {
+                std::array::from_fn(|col| {
+                    let (a, b) = (a[row][col], b[row][col]);
+
+                    match mode {
+                        Mode::And if b != 0 => a,
+                        Mode::And => 0,
+                        Mode::Xor if a != 0 && b != 0 => 0,
+                        _ if b != 0 => b,
+                        _ => a,
+                    }
+                })
+            }))
+        } else {
+            let (Some(a), Some(b)) = (cell_a.children(), cell_b.children()) else {
+                unreachable!("Only cells 8 cells wide are leaves");
+            };
+
+            let [nw, ne, sw, se] =
+                [0, 1, 2, 3].map(|i| self.combine(a[i], b[i], depth - 1, mode, memo));
+
+            self.buf.insert(Cell::new(nw, ne, sw, se))
+        };
+
+        memo.insert((a, b), n);
+
+        n
+    }
+
+    /// Every place `pattern` occurs at in any of `orientations`, as `(x, y, orientation)` where
+    /// `Transform::new(orientation, x, y)` moves the pattern onto its match. Orientations under
+    /// which the pattern looks the same only report their first match. A pattern without live
+    /// cells matches nothing.
+    ///
+    /// The first live cell of the pattern can only land on a live cell of the world, where the
+    /// bounding box of the live cells of the pattern still fits within that of the world. Those
+    /// are found by walking down the quadtree past void cells and cells outside of that region,
+    /// and the other cells of the pattern are then looked up one by one.
+    pub fn find(
+        &self,
+        pattern: &Pattern,
+        orientations: &[Orientation],
+    ) -> Vec<(WorldOffset, WorldOffset, Orientation)> {
+        let Some(bbox) = self.bounding_box() else {
+            return Vec::new();
+        };
+
+        let mut found = Vec::new();
+        let mut seen = Vec::new();
+
+        for &orientation in orientations {
+            let oriented = |cells: &[(WorldOffset, WorldOffset)]| {
+                let mut cells: Vec<_> = cells
+                    .iter()
+                    .map(|&(x, y)| orientation.apply(x, y))
+                    .collect();
+                cells.sort();
+                cells
+            };
+
+            let (live, dead) = (oriented(&pattern.live), oriented(&pattern.dead));
+
+            let Some(&(ax, ay)) = live.first() else {
+                return Vec::new();
+            };
+
+            // Symmetric patterns look the same in more than one orientation, up to translation
+            let shape = |cells: &[(WorldOffset, WorldOffset)]| -> Vec<_> {
+                cells.iter().map(|&(x, y)| (x - ax, y - ay)).collect()
+            };
+            let shapes = (shape(&live), shape(&dead));
+
+            if seen.contains(&shapes) {
+                continue;
+            }
+
+            seen.push(shapes);
+
+            // Where the first live cell can land, for the live cells to fit in the bounding box
+            let (x0, x1) = live
+                .iter()
+                .fold((ax, ax), |(x0, x1), &(x, _)| (x0.min(x), x1.max(x)));
+            let (y0, y1) = live
+                .iter()
+                .fold((ay, ay), |(y0, y1), &(_, y)| (y0.min(y), y1.max(y)));
+
+            let (Some(w), Some(h)) = (
+                bbox.w.checked_sub((x1 - x0) as u64),
+                bbox.h.checked_sub((y1 - y0) as u64),
+            ) else {
+                continue;
+            };
+
+            let anchors = Rect::new(bbox.x + ax - x0, bbox.y - (y1 - ay), w, h);
+
+            for (x, y) in self.live_cells_in(anchors) {
+                let (dx, dy) = (x - ax, y - ay);
+
+                let alive = |&(x, y): &(WorldOffset, WorldOffset)| self.state(x + dx, y + dy) != 0;
+
+                if live.iter().all(alive) && !dead.iter().any(alive) {
+                    found.push((dx, dy, orientation));
+                }
+            }
+        }
+
+        found.sort_by_key(|&(x, y, orientation)| (x, y, orientation as u8));
+
+        found
+    }
+
+    /// Drop every cell that isn't part of the world anymore, along with their memoized results.
+    ///
+    /// This happens on its own as the world is stepped, but can be done by hand to free up
+    /// memory.
+    pub fn compact(&mut self) {
+        let (buf, roots) = self.buf.compact(&[self.root]);
+
+        self.buf = buf;
+        self.root = roots[0];
+        self.compact_len = MIN_COMPACT_LEN.max(2 * self.buf.len());
+    }
+
+    /// Check whether every live cell lies within the center `2^{depth - 2}` cell of the world
+    fn is_centered(&self) -> bool {
+        let q = 1 << (self.depth - 3);
+
+        let Some(bbox) = self.bounding_box() else {
+            return true;
+        };
+
+        -q <= bbox.x
+            && bbox.x + bbox.w as WorldOffset <= q
+            && bbox.y < q
+            && bbox.y - bbox.h as WorldOffset >= -q - 1
+    }
+
+    /// Iterate over the coordinates of every live cell. With Generations rules, those are the
+    /// cells in any state but 0.
+    pub fn live_cells(&self) -> LiveCells<'_> {
+        LiveCells::new(self, None)
+    }
+
+    /// Iterate over the coordinates of every live cell within `rect`
+    pub fn live_cells_in(&self, rect: Rect) -> LiveCells<'_> {
+        LiveCells::new(self, Some(rect))
+    }
+
+    /// The smallest region containing every live cell, or `None` if the world is empty
+    pub fn bounding_box(&self) -> Option<Rect> {
+        let half = 1 << (self.depth - 1);
+
+        let mut bounds = None;
+        self.bounds(self.root, self.depth, -half, half - 1, &mut bounds);
+
+        bounds.map(|(x0, x1, y0, y1)| {
+            let (w, h) = (x1 - x0 + 1, y1 - y0 + 1);
+
+            Rect::new(x0, y1, w as u64, h as u64)
+        })
+    }
+
+    /// Grow `bounds`, given as `(min x, max x, min y, max y)`, to include the live cells of the
+    /// `2^depth` cell at `ptr`. The top left cell of the cell is (`x`, `y`).
+    fn bounds(
+        &self,
+        ptr: usize,
+        depth: u8,
+        x: WorldOffset,
+        y: WorldOffset,
+        bounds: &mut Option<(WorldOffset, WorldOffset, WorldOffset, WorldOffset)>,
+    ) {
+        let cell = self.buf[ptr];
+
+        if cell.is_void() {
+            return;
         }
-    }
 
-    pub fn next(&mut self) {
-        let mut root = self.buf[self.root];
+        let size = 1 << depth;
+
+        // Nothing in here can grow the bounds any further
+        if let Some((x0, x1, y0, y1)) = *bounds
+            && x0 <= x
+            && x + size - 1 <= x1
+            && y0 <= y - size + 1
+            && y <= y1
+        {
+            return;
+        }
 
-        self.root = root.next(&self.rules, &mut self.buf);
-        self.depth -= 1;
+        if cell.is_leaf() {
+            for (dx, dy) in cell.leaf_cells(&self.buf) {
+                let (x, y) = (x + dx as WorldOffset, y - dy as WorldOffset);
 
-        self.grow(1);
+                *bounds = Some(match *bounds {
+                    None => (x, x, y, y),
+                    Some((x0, x1, y0, y1)) => (x0.min(x), x1.max(x), y0.min(y), y1.max(y)),
+                });
+            }
+        } else {
+            let half = size >> 1;
+
+            self.bounds(cell.nw, depth - 1, x, y, bounds);
+            self.bounds(cell.ne, depth - 1, x + half, y, bounds);
+            self.bounds(cell.sw, depth - 1, x, y - half, bounds);
+            self.bounds(cell.se, depth - 1, x + half, y - half, bounds);
+        }
     }
 
     /// Grows the world by a factor of 2^k, keeping the previous root at the origin
@@ -56,14 +1361,55 @@ impl World {
 
         let root = self.buf[self.root];
         let root = root.grow(&mut self.buf);
-        self.buf[self.root] = root;
+        self.root = self.buf.insert(root);
 
         self.depth += 1;
 
         self.grow(k - 1);
     }
 
+    /// Turn on the cell at (`x`, `y`)
     pub fn set(&mut self, x: WorldOffset, y: WorldOffset) {
+        self.set_state(x, y, 1);
+    }
+
+    /// The state of the cell at (`x`, `y`), which is 0 outside of the world
+    pub fn state(&self, x: WorldOffset, y: WorldOffset) -> u8 {
+        let w = 1 << (self.depth - 1);
+
+        if !(-w <= x && x < w && -w <= y && y < w) {
+            return 0;
+        }
+
+        let (mut ptr, mut x, mut y) = (self.root, x, y);
+
+        for depth in (4..=self.depth).rev() {
+            let w = 1 << depth;
+            let f = |c| c - if c < 0 { -(w >> 2) } else { w >> 2 };
+
+            ptr = Self::get_quadrant(self.buf[ptr], x, y);
+            (x, y) = (f(x), f(y));
+        }
+
+        let cell = self.buf[ptr];
+
+        if cell.is_void() {
+            return 0;
+        }
+
+        let quad = Self::get_quadrant(cell, x, y) & !LEAF_MASK;
+
+        self.buf.block(quad)[Self::block_index(x, y)]
+    }
+
+    /// Set the cell at (`x`, `y`) to `state`. With 2 states, any state but 0 turns the cell on.
+    pub fn set_state(&mut self, x: WorldOffset, y: WorldOffset, state: u8) {
+        assert!(
+            (state as u16) < self.rule.states(),
+            "The rule has {} states, got state {state}",
+            self.rule.states()
+        );
+
         let root = self.root;
 
         let w = 1 << (self.depth - 1);
@@ -84,45 +1430,58 @@ impl World {
             y
         );
 
-        self.set_bit(root, x, y, self.depth);
+        self.root = self.set_bit(root, x, y, state, self.depth);
     }
 
-    fn set_bit(&mut self, ptr: usize, x: WorldOffset, y: WorldOffset, depth: u8) {
+    /// Index of the cell at (`x`, `y`) in its 4x4 block, see [`CellBuf::block`]
+    fn block_index(x: WorldOffset, y: WorldOffset) -> usize {
+        (4 * (3 - (y & 3)) + (x & 3)) as usize
+    }
+
+    /// Set a cell in the `2^depth` cell at `ptr` to `state` and return the index of the resulting
+    /// cell.
+    ///
+    /// Cells may be shared, so this never modifies a cell in place. Instead, every cell along the
+    /// way is copied.
+    fn set_bit(
+        &mut self,
+        ptr: usize,
+        x: WorldOffset,
+        y: WorldOffset,
+        state: u8,
+        depth: u8,
+    ) -> usize {
         assert!(depth >= 3);
 
+        let mut cell = self.buf[ptr];
+
         if depth == 3 {
             // Leaf
-            let cell = &mut self.buf[ptr];
+            if cell.is_void() {
+                cell = Cell::leaf_uninit();
+            }
+
+            // The leaf mask gets in the way of the north west block
+            cell.nw &= !LEAF_MASK;
 
-            let quad = Self::get_quadrant_mut(cell, x, y);
-            *quad |= 1 << (3 - (x & 3) + 4 * (y & 3));
+            let quad = *Self::get_quadrant_mut(&mut cell, x, y);
+            let mut block = self.buf.block(quad);
+            block[Self::block_index(x, y)] = state;
+
+            *Self::get_quadrant_mut(&mut cell, x, y) = self.buf.insert_block(block);
+            cell = Cell::leaf(cell.nw, cell.ne, cell.sw, cell.se);
         } else {
             // Non-leaf
-            let cell = self.buf[ptr];
             let quad = Self::get_quadrant(cell, x, y);
 
             let w = 1 << depth;
             let f = |c| c - if c < 0 { -(w >> 2) } else { w >> 2 };
 
-            // We're pointing at nothing
-            if quad == 0 {
-                // Depth 4 means our child should be a leaf
-                let new_child_ptr = if depth == 4 {
-                    self.add_leaf()
-                } else {
-                    self.add_node()
-                };
-
-                let cell = &mut self.buf[ptr];
-
-                let quad = Self::get_quadrant_mut(cell, x, y);
-                *quad = new_child_ptr;
-
-                self.set_bit(new_child_ptr, f(x), f(y), depth - 1)
-            } else {
-                self.set_bit(quad, f(x), f(y), depth - 1)
-            }
+            let child = self.set_bit(quad, f(x), f(y), state, depth - 1);
+            *Self::get_quadrant_mut(&mut cell, x, y) = child;
         }
+
+        self.buf.insert(cell)
     }
 
     #[allow(clippy::collapsible_else_if)]
@@ -158,22 +1517,732 @@ impl World {
             }
         }
     }
+}
 
-    /// Add a leaf cell to the world and return its index
-    fn add_leaf(&mut self) -> usize {
-        let n = self.buf.len();
+/// Iterator over the live cells of a [`World`], in no particular order.
+///
+/// See: [`World::live_cells`]
+pub struct LiveCells<'a> {
+    buf: &'a CellBuf,
 
-        self.buf.push(Cell::leaf_uninit());
+    /// Only cells within this region are yielded
+    rect: Option<Rect>,
 
-        n
+    /// Cells left to visit, given as `(index, depth, x, y)` where (`x`, `y`) is their top left
+    stack: Vec<(usize, u8, WorldOffset, WorldOffset)>,
+
+    /// Live cells of the last leaf we visited
+    pending: Vec<(WorldOffset, WorldOffset)>,
+}
+
+impl<'a> LiveCells<'a> {
+    fn new(world: &'a World, rect: Option<Rect>) -> Self {
+        let half = 1 << (world.depth - 1);
+
+        Self {
+            buf: &world.buf,
+            rect,
+            stack: vec![(world.root, world.depth, -half, half - 1)],
+            pending: Vec::new(),
+        }
     }
+}
 
-    /// Add a non-leaf cell to the world and return its index
-    fn add_node(&mut self) -> usize {
-        let n = self.buf.len();
+impl Iterator for LiveCells<'_> {
+    type Item = (WorldOffset, WorldOffset);
 
-        self.buf.push(Cell::uninit());
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(cell) = self.pending.pop() {
+                return Some(cell);
+            }
 
-        n
+            let (ptr, depth, x, y) = self.stack.pop()?;
+            let cell = self.buf[ptr];
+
+            if cell.is_void() {
+                continue;
+            }
+
+            if let Some(rect) = self.rect
+                && !rect.overlaps(x, y, 1 << depth)
+            {
+                continue;
+            }
+
+            if cell.is_leaf() {
+                let cells = cell
+                    .leaf_cells(self.buf)
+                    .map(|(dx, dy)| (x + dx as WorldOffset, y - dy as WorldOffset))
+                    .filter(|&(x, y)| self.rect.is_none_or(|rect| rect.contains(x, y)));
+
+                self.pending.extend(cells);
+            } else {
+                let half = 1 << (depth - 1);
+
+                self.stack.push((cell.se, depth - 1, x + half, y - half));
+                self.stack.push((cell.sw, depth - 1, x, y - half));
+                self.stack.push((cell.ne, depth - 1, x + half, y));
+                self.stack.push((cell.nw, depth - 1, x, y));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::WorldOffset;
+    use crate::raster::Raster;
+    use crate::rule_set::B3S23;
+    use crate::rule_set::RuleSet;
+    use crate::transform::Orientation;
+    use crate::transform::Transform;
+
+    use super::DiffResult;
+    use super::GLIDER;
+    use super::Mode;
+    use super::Pattern;
+    use super::Rect;
+    use super::World;
+    use super::glider;
+
+    #[test]
+    fn test_step_glider() {
+        let mut world = glider();
+
+        let before = Raster::new(&world, Rect::new(0, 2, 3, 3), 1);
+
+        world.step(1);
+        world.step(3);
+
+        // After 4 generations, a glider has moved diagonally by one cell
+        let after = Raster::new(&world, Rect::new(1, 1, 3, 3), 1);
+
+        assert_eq!(world.generation(), 4);
+        assert_eq!(world.bounding_box(), Some(Rect::new(1, 1, 3, 3)));
+        assert_eq!(before.pixels(), after.pixels());
+    }
+
+    #[test]
+    fn test_live_cells() {
+        let mut world = World::new(B3S23);
+        world.grow(2);
+
+        for (x, y) in [(-16, 15), (0, 0), (3, -1), (15, -16)] {
+            world.set(x, y);
+        }
+
+        let mut cells: Vec<_> = world.live_cells().collect();
+        cells.sort();
+
+        assert_eq!(cells, [(-16, 15), (0, 0), (3, -1), (15, -16)]);
+
+        let mut cells: Vec<_> = world.live_cells_in(Rect::new(0, 0, 4, 2)).collect();
+        cells.sort();
+
+        assert_eq!(cells, [(0, 0), (3, -1)]);
+    }
+
+    #[test]
+    fn test_step_b0() {
+        // B0/S: every cell without live neighbours is born, nothing survives
+        let mut world = World::new(RuleSet::new(0b1, 0));
+        world.set(0, 0);
+
+        // Every cell but the ones around the origin turns on, so the world holds those instead
+        world.step(1);
+
+        assert!(world.background());
+        assert_eq!(world.live_cells().count(), 9);
+        assert_eq!(world.bounding_box(), Some(Rect::new(-1, 1, 3, 3)));
+
+        // Only the origin has no live neighbours
+        world.step(1);
+
+        assert!(!world.background());
+        assert_eq!(world.live_cells().collect::<Vec<_>>(), [(0, 0)]);
+    }
+
+    #[test]
+    fn test_step_generations() {
+        // Brian's Brain
+        let mut world = World::new(RuleSet::new(0b100, 0).generations(3));
+
+        world.set(0, 0);
+        world.set(1, 0);
+
+        // Cells with exactly 2 live neighbours are born, and live cells start dying
+        world.step(1);
+
+        let mut cells: Vec<_> = world
+            .live_cells()
+            .map(|(x, y)| (x, y, world.state(x, y)))
+            .collect();
+        cells.sort();
+
+        assert_eq!(
+            cells,
+            [
+                (0, -1, 1),
+                (0, 0, 2),
+                (0, 1, 1),
+                (1, -1, 1),
+                (1, 0, 2),
+                (1, 1, 1)
+            ]
+        );
+
+        // Dying cells don't count as live neighbours, and die for good
+        world.step(1);
+
+        assert_eq!(world.state(0, 0), 0);
+        assert_eq!(world.state(0, 1), 2);
+        assert_eq!(world.state(0, 2), 1);
+    }
+
+    #[test]
+    fn test_step_torus() {
+        let rule = "B3/S23:T10,7".parse().unwrap();
+        let mut world = World::new(rule);
+
+        assert_eq!(world.grid(), Some(Rect::new(-5, 3, 10, 7)));
+
+        for (x, y) in GLIDER {
+            world.set(x, y);
+        }
+
+        let mut glider: Vec<_> = world.live_cells().collect();
+        glider.sort();
+
+        // The glider wraps around the edges, and never leaves the grid
+        for _ in 0..70 {
+            world.step(4);
+
+            let bbox = world.bounding_box().unwrap();
+            assert!(bbox.w <= 10 && bbox.h <= 7);
+        }
+
+        // It has moved by 70 cells both ways, back where it started
+        let mut cells: Vec<_> = world.live_cells().collect();
+        cells.sort();
+
+        assert_eq!(cells, glider);
+    }
+
+    #[test]
+    fn test_step_bounded_plane() {
+        let rule = "B3/S23:P10,7".parse().unwrap();
+        let mut world = World::new(rule);
+        world.grow(1);
+
+        for (x, y) in GLIDER {
+            world.set(x, y);
+        }
+
+        // Cells past the edges are dead, outside cells too
+        world.set(-6, 0);
+
+        // The glider crashes into the south east corner, and turns into a block
+        world.step(24);
+
+        let mut cells: Vec<_> = world.live_cells().collect();
+        cells.sort();
+
+        assert_eq!(cells, [(3, -3), (3, -2), (4, -3), (4, -2)]);
+    }
+
+    #[test]
+    fn test_step_klein_bottle() {
+        let mut torus = World::new("B3/S23:T10,7".parse().unwrap());
+        let mut klein = World::new("B3/S23:K10*,7".parse().unwrap());
+
+        for (x, y) in GLIDER {
+            torus.set(x, y);
+            klein.set(x, y);
+        }
+
+        // The glider crosses the bottom edge once, which mirrors it on a Klein bottle
+        torus.step(28);
+        klein.step(28);
+
+        let mut mirrored: Vec<_> = torus.live_cells().map(|(x, y)| (-1 - x, y)).collect();
+        mirrored.sort();
+
+        let mut cells: Vec<_> = klein.live_cells().collect();
+        cells.sort();
+
+        assert_eq!(cells, mirrored);
+    }
+
+    #[test]
+    fn test_step_shifted_torus() {
+        // Golly glues the edges of a torus shifted like `:T4+1,3` as:
+        //
+        //   k l i j k l
+        //   d A B C D a
+        //   h E F G H e
+        //   l I J K L i
+        //   a b c d a b
+        //
+        // So a cell leaving through the bottom comes back through the top `s` columns to the
+        // right, and one leaving through the right comes back through the left `s` rows lower.
+        // A glider on the torus is the glider on the plane, wrapped around like this.
+        for (rule, (sx, sy)) in [
+            ("B3/S23:T30+5,20", (5, 0)),
+            ("B3/S23:T30,20+5", (0, 5)),
+            ("B3/S23:T30,0", (0, 0)),
+        ] {
+            let mut torus = World::new(rule.parse().unwrap());
+            let mut plane = glider();
+
+            for (x, y) in GLIDER {
+                torus.set(x, y);
+            }
+
+            for _ in 0..60 {
+                torus.step(4);
+                plane.step(4);
+
+                let grid = torus.grid().unwrap();
+                let (w, h) = (grid.w as WorldOffset, grid.h as WorldOffset);
+                let bottom = grid.y - h + 1;
+
+                let wrap = |(mut x, mut y): (WorldOffset, WorldOffset)| {
+                    if sy == 0 {
+                        // Unless it's a cylinder
+                        if torus.rule().extension().unwrap().height > 0 {
+                            let k = (y - bottom).div_euclid(h);
+                            (x, y) = (x - k * sx, y - k * h);
+                        }
+
+                        (grid.x + (x - grid.x).rem_euclid(w), y)
+                    } else {
+                        let k = (x - grid.x).div_euclid(w);
+                        (x, y) = (x - k * w, y - k * sy);
+
+                        (x, bottom + (y - bottom).rem_euclid(h))
+                    }
+                };
+
+                let mut wrapped: Vec<_> = plane.live_cells().map(wrap).collect();
+                wrapped.sort();
+
+                let mut cells: Vec<_> = torus.live_cells().collect();
+                cells.sort();
+
+                assert_eq!(
+                    cells,
+                    wrapped,
+                    "{rule} at generation {}",
+                    torus.generation()
+                );
+            }
+        }
+    }
+
+    /// One generation of B3/S23 on a `w` by `h` grid, with cells given by column from the left
+    /// and row from the top. `glue` gives the cell of the grid which a cell right past its edges
+    /// stands for.
+    fn step_glued(
+        cells: &HashSet<(WorldOffset, WorldOffset)>,
+        (w, h): (WorldOffset, WorldOffset),
+        glue: &dyn Fn(WorldOffset, WorldOffset) -> (WorldOffset, WorldOffset),
+    ) -> HashSet<(WorldOffset, WorldOffset)> {
+        let alive = |c, r| match (0..w).contains(&c) && (0..h).contains(&r) {
+            true => cells.contains(&(c, r)),
+            false => cells.contains(&glue(c, r)),
+        };
+
+        let mut next = HashSet::new();
+        for c in 0..w {
+            for r in 0..h {
+                let n = (-1..=1)
+                    .flat_map(|dc| (-1..=1).map(move |dr| (c + dc, r + dr)))
+                    .filter(|&(x, y)| (x, y) != (c, r) && alive(x, y))
+                    .count();
+
+                if n == 3 || n == 2 && alive(c, r) {
+                    next.insert((c, r));
+                }
+            }
+        }
+
+        next
+    }
+
+    #[test]
+    fn test_step_sphere_and_cross_surface() {
+        // Golly glues the edges of a sphere like `:S3`, and of a cross-surface like `:C4,3`, as:
+        //
+        //   a a d g c      a l k j i d
+        //   a A B C g      l A B C D i
+        //   b D E F h      h E F G H e
+        //   c G H I i      d I J K L a
+        //   g c f i i      i d c b a l
+        //
+        // Each corner past the grid is a copy of the corner of the grid next to it
+        let corner = |c: WorldOffset, r: WorldOffset, (w, h)| {
+            let outside = |n, size| n < 0 || n >= size;
+            (outside(c, w) && outside(r, h)).then(|| (c.clamp(0, w - 1), r.clamp(0, h - 1)))
+        };
+
+        let sphere = |c, r| {
+            let (w, h) = (20, 20);
+            corner(c, r, (w, h)).unwrap_or(match (c, r) {
+                (_, -1) => (0, c),
+                (-1, _) => (r, 0),
+                (_, r) if r == h => (w - 1, c),
+                _ => (r, h - 1),
+            })
+        };
+
+        let cross_surface = |c, r| {
+            let (w, h) = (20, 16);
+            corner(c, r, (w, h)).unwrap_or(match (c, r) {
+                (_, -1) => (w - 1 - c, h - 1),
+                (_, r) if r == h => (w - 1 - c, 0),
+                (-1, _) => (w - 1, h - 1 - r),
+                _ => (0, h - 1 - r),
+            })
+        };
+
+        // Gliders heading across the edges, then into the corners
+        let south_east = GLIDER;
+        let north_east = GLIDER.map(|(x, y)| (x, -y));
+
+        let starts = [
+            (south_east, (0, 0)),
+            (south_east, (-6, 2)),
+            (north_east, (-8, -5)),
+        ];
+
+        let topologies: [(_, &dyn Fn(_, _) -> _); 2] =
+            [("B3/S23:S20", &sphere), ("B3/S23:C20,16", &cross_surface)];
+
+        for (rule, glue) in topologies {
+            for (glider, (dx, dy)) in starts {
+                let mut world = World::new(rule.parse().unwrap());
+                world.grow(2);
+
+                for (x, y) in glider {
+                    world.set(x + dx, y + dy);
+                }
+
+                let grid = world.grid().unwrap();
+                let size = (grid.w as WorldOffset, grid.h as WorldOffset);
+                let in_grid = |world: &World| -> HashSet<_> {
+                    world
+                        .live_cells()
+                        .map(|(x, y)| (x - grid.x, grid.y - y))
+                        .collect()
+                };
+
+                let mut cells = in_grid(&world);
+
+                for _ in 0..160 {
+                    world.step(1);
+                    cells = step_glued(&cells, size, glue);
+
+                    assert_eq!(
+                        in_grid(&world),
+                        cells,
+                        "{rule} from ({dx}, {dy}) at generation {}",
+                        world.generation()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_period() {
+        let period = |cells: &[(i128, i128)]| {
+            let mut world = World::new(B3S23);
+
+            for &(x, y) in cells {
+                world.set(x, y);
+            }
+
+            world.detect_period(100)
+        };
+
+        let glider = period(&GLIDER).unwrap();
+        assert_eq!((glider.period, glider.dx, glider.dy), (4, 1, -1));
+        assert!(glider.is_spaceship());
+
+        let blinker = period(&[(-1, 0), (0, 0), (1, 0)]).unwrap();
+        assert_eq!((blinker.period, blinker.dx, blinker.dy), (2, 0, 0));
+        assert!(blinker.is_oscillator());
+
+        // Turns into a block after a generation
+        let block = period(&[(0, 0), (1, 0), (0, 1)]).unwrap();
+        assert!(block.is_still_life());
+        assert_eq!((glider.start, blinker.start, block.start), (0, 0, 1));
+
+        assert!(period(&[]).unwrap().is_still_life());
+
+        // The R-pentomino takes over a thousand generations to settle
+        assert_eq!(period(&[(0, 1), (1, 1), (-1, 0), (0, 0), (0, -1)]), None);
+    }
+
+    #[test]
+    fn test_run_until_stable() {
+        let mut world = World::new(B3S23);
+
+        for (x, y) in [(0, 1), (1, 1), (-1, 0), (0, 0), (0, -1)] {
+            world.set(x, y);
+        }
+
+        assert_eq!(world.run_until_stable(50, 10, false), None);
+        assert_eq!(world.generation(), 50);
+
+        // Even though its 6 gliders keep going
+        world.step(1000);
+        let stabilisation = world.run_until_stable(100, 10, false).unwrap();
+        assert_eq!(
+            (stabilisation.generation, stabilisation.population),
+            (1103, 116)
+        );
+        assert_eq!(stabilisation.periodicity.period, 2);
+        assert_eq!(stabilisation.periodicity.start, 53);
+
+        // A lone glider escapes from nothing, leaving an empty world behind
+        let mut world = glider();
+
+        let stabilisation = world.run_until_stable(100, 10, false).unwrap();
+        assert_eq!((stabilisation.generation, stabilisation.population), (0, 5));
+        assert!(stabilisation.periodicity.is_still_life());
+
+        // Unless it is removed as soon as it is found
+        let mut world = glider();
+
+        let stabilisation = world.run_until_stable(100, 10, true).unwrap();
+        assert_eq!((stabilisation.generation, stabilisation.population), (0, 0));
+        assert_eq!(world.live_cells().count(), 0);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut before = World::new(B3S23);
+        let mut after = World::new(B3S23);
+
+        for (x, y) in [(-1, 0), (0, 0), (1, 0)] {
+            before.set(x, y);
+            after.set(x, y);
+        }
+
+        // Only the blinker differs, the block is the same in both
+        for world in [&mut before, &mut after] {
+            world.grow(3);
+
+            for (x, y) in [(20, 20), (21, 20), (20, 21), (21, 21)] {
+                world.set(x, y);
+            }
+        }
+
+        after.step(1);
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.births, [(0, -1), (0, 1)]);
+        assert_eq!(diff.deaths, [(-1, 0), (1, 0)]);
+
+        // Worlds of different sizes are lined up around the origin
+        let reversed = DiffResult {
+            births: diff.deaths,
+            deaths: diff.births,
+        };
+
+        after.grow(1);
+        assert_eq!(after.diff(&before), reversed);
+        assert_eq!(after.diff(&after), DiffResult::default());
+    }
+
+    #[test]
+    fn test_transform() {
+        let cells = [(0, 0, 1), (1, 0, 2), (-1, 2, 1), (5, -3, 2), (-20, 9, 1)];
+
+        // Moved by nothing, by odd offsets, and by more than the world is wide
+        let offsets = [(0, 0), (5, -3), (-37, 100), (1000, 1)];
+
+        // Brian's Brain, whose leaves hold blocks of states rather than rules
+        for rule in [B3S23, RuleSet::new(0b100, 0).generations(3)] {
+            let states = rule.states() as u8;
+            let mut world = World::new(rule);
+            world.grow(3);
+
+            let mut expected: Vec<_> = cells
+                .iter()
+                .map(|&(x, y, state)| (x, y, state.min(states - 1)))
+                .collect();
+
+            for &(x, y, state) in &expected {
+                world.set_state(x, y, state);
+            }
+
+            for (i, orientation) in Orientation::ALL.into_iter().enumerate() {
+                let (dx, dy) = offsets[i % offsets.len()];
+                let transform = Transform::new(orientation, dx, dy);
+                world.transform(transform);
+
+                for (x, y, _) in &mut expected {
+                    (*x, *y) = transform.apply(*x, *y);
+                }
+                expected.sort();
+
+                let mut cells: Vec<_> = world
+                    .live_cells()
+                    .map(|(x, y)| (x, y, world.state(x, y)))
+                    .collect();
+                cells.sort();
+
+                assert_eq!(cells, expected, "{transform:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_paste() {
+        let ours = [(0, 0), (1, 0), (0, 1), (1, 1), (5, 5), (-3, -4)];
+        let theirs = [(-3, 3), (-2, 3), (-3, 2), (30, -30)];
+        let (dx, dy) = (3, -2);
+
+        let moved: Vec<_> = theirs.iter().map(|&(x, y)| (x + dx, y + dy)).collect();
+        let bbox = Rect::new(0, 1, 34, 34);
+
+        let mut other = World::new(B3S23);
+        other.grow(3);
+
+        for (x, y) in theirs {
+            other.set(x, y);
+        }
+
+        for mode in [Mode::Or, Mode::And, Mode::Xor, Mode::Copy] {
+            let mut world = World::new(B3S23);
+            world.grow(1);
+
+            for (x, y) in ours {
+                world.set(x, y);
+            }
+
+            world.paste(&other, dx, dy, mode);
+
+            let mut cells: Vec<_> = world.live_cells().collect();
+            cells.sort();
+
+            let mut expected: Vec<_> = match mode {
+                Mode::Or => ours.iter().chain(&moved).copied().collect(),
+                Mode::And => ours.into_iter().filter(|c| moved.contains(c)).collect(),
+                Mode::Xor => ours
+                    .iter()
+                    .filter(|c| !moved.contains(c))
+                    .chain(moved.iter().filter(|c| !ours.contains(c)))
+                    .copied()
+                    .collect(),
+                Mode::Copy => ours
+                    .into_iter()
+                    .filter(|&(x, y)| !bbox.contains(x, y))
+                    .chain(moved.iter().copied())
+                    .collect(),
+            };
+            expected.sort();
+            expected.dedup();
+
+            assert_eq!(cells, expected, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_extract_and_clear() {
+        let mut world = World::new(B3S23);
+        world.grow(3);
+
+        // Crosses the edges of leaves, but not the edges of the world
+        let rect = Rect::new(-3, 4, 10, 9);
+
+        let inside = [(-3, 4), (0, 0), (6, -4)];
+        let outside = [(-20, 20), (-4, 4), (6, 5), (7, -4)];
+
+        for (x, y) in inside.into_iter().chain(outside).chain([(15, -15)]) {
+            world.set(x, y);
+        }
+
+        let sorted = |world: &World| {
+            let mut cells: Vec<_> = world.live_cells().collect();
+            cells.sort();
+            cells
+        };
+
+        assert_eq!(sorted(&world.extract(rect)), inside);
+
+        world.clear_outside(Rect::new(-20, 20, 30, 30));
+        assert_eq!(world.state(15, -15), 0);
+
+        world.clear_rect(rect);
+        assert_eq!(sorted(&world), outside);
+    }
+
+    #[test]
+    fn test_find() {
+        let r_pentomino = [(0, 1), (1, 1), (-1, 0), (0, 0), (0, -1)];
+
+        let mut pattern = World::new(B3S23);
+        let mut envelope = World::new(B3S23);
+        let mut world = World::new(B3S23);
+        world.grow(3);
+
+        for (x, y) in r_pentomino {
+            pattern.set(x, y);
+        }
+
+        for x in -2..=2 {
+            for y in -2..=2 {
+                envelope.set(x, y);
+            }
+        }
+
+        let placements = [
+            (-20, 20, Orientation::Identity),
+            (0, -20, Orientation::FlipX),
+            (15, 10, Orientation::Rotate90),
+        ];
+
+        for (dx, dy, orientation) in placements {
+            for (x, y) in r_pentomino {
+                let (x, y) = Transform::new(orientation, dx, dy).apply(x, y);
+                world.set(x, y);
+            }
+        }
+
+        // Right next to the mirrored R-pentomino, without touching it
+        world.set(1, -18);
+
+        let pattern = Pattern::new(&pattern);
+        assert_eq!(world.find(&pattern, &Orientation::ALL), placements);
+
+        let pattern = pattern.with_envelope(&envelope);
+        assert_eq!(
+            world.find(&pattern, &Orientation::ALL),
+            [placements[0], placements[2]]
+        );
+        assert_eq!(
+            world.find(&pattern, &[Orientation::Identity]),
+            [placements[0]]
+        );
+
+        // A single cell looks the same in every orientation, and matches every live cell once
+        let cell = Pattern {
+            live: vec![(0, 0)],
+            dead: Vec::new(),
+        };
+        assert_eq!(world.find(&cell, &Orientation::ALL).len(), 16);
+
+        // While a pattern made only of dead cells matches nothing
+        let empty = Pattern {
+            live: Vec::new(),
+            dead: vec![(0, 0)],
+        };
+        assert_eq!(world.find(&empty, &Orientation::ALL), []);
     }
 }